@@ -0,0 +1,64 @@
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime, Utc};
+use clap::{App, Arg};
+use local::analyzers::backtest::run_backtest;
+use local::storage::establish_connection;
+use local::storage::write::write_backtest_results;
+
+#[tokio::main]
+pub async fn main() -> Result<()> {
+    let matches = App::new("backtest")
+        .about("Replays the stored sales of a collection against every pricing strategy")
+        .arg(
+            Arg::with_name("collection")
+                .help("slug of the collection to backtest")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("days_back")
+                .long("days-back")
+                .takes_value(true)
+                .help("only replay sales of the last N days"),
+        )
+        .arg(
+            Arg::with_name("max_sales")
+                .long("max-sales")
+                .takes_value(true)
+                .help("only replay the N most recent sales"),
+        )
+        .get_matches();
+
+    let collection_slug = matches.value_of("collection").unwrap();
+    let since = match matches.value_of("days_back") {
+        Some(d) => (Utc::now() - Duration::days(d.parse()?)).naive_utc(),
+        None => NaiveDateTime::from_timestamp(0, 0),
+    };
+    let max_sales = match matches.value_of("max_sales") {
+        Some(n) => Some(n.parse()?),
+        None => None,
+    };
+
+    let pool = establish_connection().await;
+    let mut conn = pool.acquire().await?;
+
+    let results = run_backtest(&mut conn, collection_slug, &since, max_sales).await?;
+    write_backtest_results(&mut conn, &results).await?;
+
+    println!(
+        "{:<36}{:>10}{:>10}{:>12}{:>10}{:>10}",
+        "strategy", "sales", "priced", "mae", "mape", "hit rate"
+    );
+    for r in results {
+        println!(
+            "{:<36}{:>10}{:>10}{:>12.4}{:>10.4}{:>10}",
+            r.strategy,
+            r.nr_sales,
+            r.nr_priced,
+            r.mae.unwrap_or_default(),
+            r.mape.unwrap_or_default(),
+            r.hit_rate.map(|h| format!("{:.2}", h)).unwrap_or_default(),
+        );
+    }
+
+    Ok(())
+}
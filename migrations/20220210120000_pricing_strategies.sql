@@ -0,0 +1,2 @@
+ALTER TABLE COLLECTION
+ADD COLUMN PRICING_STRATEGIES VARCHAR[] NOT NULL DEFAULT array[]::varchar[];
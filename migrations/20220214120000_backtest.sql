@@ -0,0 +1,12 @@
+CREATE TABLE BACKTEST_RESULT (
+    collection_slug VARCHAR NOT NULL,
+    strategy VARCHAR NOT NULL,
+    nr_sales INT NOT NULL,
+    nr_priced INT NOT NULL,
+    mae float,
+    mape float,
+    hit_rate float,
+    timestamp INT NOT NULL,
+
+    primary key (collection_slug, strategy)
+);
@@ -0,0 +1,18 @@
+CREATE TABLE HEDONIC_MODEL (
+    collection_slug VARCHAR NOT NULL,
+    intercept float NOT NULL,
+    market_coefficient float NOT NULL,
+    r_squared float NOT NULL,
+    nr_sales INT NOT NULL,
+    timestamp INT NOT NULL,
+
+    primary key (collection_slug)
+);
+
+CREATE TABLE TRAIT_PREMIUM (
+    collection_slug VARCHAR NOT NULL,
+    trait_id VARCHAR NOT NULL,
+    premium float NOT NULL,
+
+    primary key (collection_slug, trait_id)
+);
@@ -0,0 +1,7 @@
+ALTER TABLE SALE
+ADD COLUMN BUYER VARCHAR,
+ADD COLUMN SELLER VARCHAR,
+ADD COLUMN FLAG VARCHAR;
+
+ALTER TABLE COLLECTION
+ADD COLUMN INCLUDE_FLAGGED_SALES BOOLEAN NOT NULL DEFAULT false;
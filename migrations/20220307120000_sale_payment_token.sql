@@ -0,0 +1,9 @@
+ALTER TABLE SALE
+ADD COLUMN PAYMENT_TOKEN VARCHAR NOT NULL DEFAULT 'ETH',
+ADD COLUMN PAYMENT_AMOUNT FLOAT,
+ADD COLUMN PAYMENT_DECIMALS INT NOT NULL DEFAULT 18;
+
+UPDATE SALE SET PAYMENT_AMOUNT = PRICE;
+
+ALTER TABLE SALE
+ALTER COLUMN PAYMENT_AMOUNT SET NOT NULL;
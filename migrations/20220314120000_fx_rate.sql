@@ -0,0 +1,6 @@
+CREATE TABLE FX_RATE (
+    timestamp INT NOT NULL,
+    eth_usd float NOT NULL,
+
+    primary key (timestamp)
+);
@@ -0,0 +1,12 @@
+CREATE TABLE PRICE_SNAPSHOT (
+    collection_slug VARCHAR NOT NULL,
+    token_id INT NOT NULL,
+    timestamp INT NOT NULL,
+    strategies JSONB NOT NULL,
+    min_price float NOT NULL,
+    avg_price float NOT NULL,
+    max_price float NOT NULL,
+    confidence float NOT NULL,
+
+    primary key (collection_slug, token_id, timestamp)
+);
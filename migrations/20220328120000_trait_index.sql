@@ -0,0 +1,12 @@
+CREATE TABLE TRAIT_INDEX (
+    collection_slug VARCHAR NOT NULL,
+    trait_id VARCHAR NOT NULL,
+    timestamp INT NOT NULL,
+    floor_price float,
+    median_price float,
+    avg_price float,
+    volume float NOT NULL,
+    nr_sales INT NOT NULL,
+
+    primary key (collection_slug, trait_id, timestamp)
+);
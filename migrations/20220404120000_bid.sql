@@ -0,0 +1,12 @@
+CREATE TABLE BID (
+    collection_slug VARCHAR NOT NULL,
+    update_type VARCHAR NOT NULL,
+    token_id INT,
+    trait_id VARCHAR,
+    bidder VARCHAR NOT NULL,
+    price float,
+    timestamp INT NOT NULL,
+    expires_at INT
+);
+
+CREATE INDEX BID_COLLECTION_TIMESTAMP ON BID (collection_slug, timestamp);
@@ -0,0 +1,4 @@
+ALTER TABLE COLLECTION
+ADD COLUMN SELLER_FEE_BASIS_POINTS INT NOT NULL DEFAULT 0,
+ADD COLUMN OPENSEA_SELLER_FEE_BASIS_POINTS INT NOT NULL DEFAULT 0,
+ADD COLUMN DEV_SELLER_FEE_BASIS_POINTS INT NOT NULL DEFAULT 0;
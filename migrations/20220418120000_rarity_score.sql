@@ -0,0 +1,12 @@
+CREATE TABLE RARITY_SCORE (
+    collection_slug VARCHAR NOT NULL,
+    token_id INT NOT NULL,
+    statistical_rarity float NOT NULL,
+    rarity_score float NOT NULL,
+    information_content float NOT NULL,
+    statistical_rarity_rank INT NOT NULL,
+    rarity_score_rank INT NOT NULL,
+    information_content_rank INT NOT NULL,
+
+    primary key (collection_slug, token_id)
+);
@@ -0,0 +1,26 @@
+ALTER TABLE COLLECTION
+ADD COLUMN OVERLAP_SIZES INT[] NOT NULL DEFAULT array[3, 4, 5];
+
+CREATE TABLE ASSET_OVERLAP (
+    collection_slug VARCHAR NOT NULL,
+    token_id INT NOT NULL,
+    combination_size INT NOT NULL,
+    nr_overlapping INT NOT NULL,
+    overlapping_ids INT[] NOT NULL,
+    primary key (collection_slug, token_id, combination_size)
+);
+
+INSERT INTO ASSET_OVERLAP
+SELECT collection_slug, token_id, 3, traits_3_combination_overlap, traits_3_combination_overlap_ids FROM ASSET
+UNION ALL
+SELECT collection_slug, token_id, 4, traits_4_combination_overlap, traits_4_combination_overlap_ids FROM ASSET
+UNION ALL
+SELECT collection_slug, token_id, 5, traits_5_combination_overlap, traits_5_combination_overlap_ids FROM ASSET;
+
+ALTER TABLE ASSET
+DROP COLUMN traits_3_combination_overlap,
+DROP COLUMN traits_4_combination_overlap,
+DROP COLUMN traits_5_combination_overlap,
+DROP COLUMN traits_3_combination_overlap_ids,
+DROP COLUMN traits_4_combination_overlap_ids,
+DROP COLUMN traits_5_combination_overlap_ids;
@@ -0,0 +1,2 @@
+ALTER TABLE COLLECTION
+ADD COLUMN ANALYSIS_WINDOW_DAYS INT;
@@ -0,0 +1,2 @@
+ALTER TABLE COLLECTION
+ADD COLUMN OWNERS_SYNCED_AT INT;
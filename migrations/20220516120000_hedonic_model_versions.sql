@@ -0,0 +1,18 @@
+ALTER TABLE TRAIT_PREMIUM
+ADD COLUMN timestamp INT;
+
+UPDATE TRAIT_PREMIUM p
+SET timestamp = m.timestamp
+FROM HEDONIC_MODEL m
+WHERE m.collection_slug = p.collection_slug;
+
+DELETE FROM TRAIT_PREMIUM WHERE timestamp IS NULL;
+
+ALTER TABLE TRAIT_PREMIUM
+ALTER COLUMN timestamp SET NOT NULL,
+DROP CONSTRAINT trait_premium_pkey,
+ADD PRIMARY KEY (collection_slug, timestamp, trait_id);
+
+ALTER TABLE HEDONIC_MODEL
+DROP CONSTRAINT hedonic_model_pkey,
+ADD PRIMARY KEY (collection_slug, timestamp);
@@ -0,0 +1,3 @@
+CREATE FUNCTION VALID_SALE(flag VARCHAR, collection_slug VARCHAR) RETURNS BOOLEAN AS $$
+    SELECT flag IS NULL OR (SELECT include_flagged_sales FROM COLLECTION WHERE slug = collection_slug)
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,11 @@
+DELETE FROM BID a
+USING BID b
+WHERE a.ctid < b.ctid
+AND a.collection_slug = b.collection_slug
+AND a.update_type = b.update_type
+AND a.token_id IS NOT DISTINCT FROM b.token_id
+AND a.trait_id IS NOT DISTINCT FROM b.trait_id
+AND a.bidder = b.bidder
+AND a.timestamp = b.timestamp;
+
+CREATE UNIQUE INDEX BID_UNIQUE ON BID (collection_slug, update_type, coalesce(token_id, -1), coalesce(trait_id, ''), bidder, timestamp);
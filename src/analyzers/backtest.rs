@@ -0,0 +1,146 @@
+use super::hedonic::refit_hedonic_model;
+use super::strategies::*;
+use crate::from_wei;
+use crate::storage::read::{read_collection, read_sales_for_collection_after_ts};
+use crate::storage::BacktestResult;
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// Name under which the combined min/max band of the enabled strategies is reported
+pub static PRICE_PROFILE: &str = "price_profile";
+
+/// Replays the stored sales of a collection, pricing every sold token with the state
+/// just before the sale and comparing the strategies against the actual sale price
+pub async fn run_backtest(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    since: &NaiveDateTime,
+    max_sales: Option<usize>,
+) -> Result<Vec<BacktestResult>> {
+    let collection = read_collection(conn, collection_slug).await?;
+    let strategies = registry();
+
+    let sales = read_sales_for_collection_after_ts(conn, collection_slug, since).await?;
+    let sales = sales
+        .into_iter()
+        .take(max_sales.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+
+    let mut samples = HashMap::<String, Vec<(f64, f64)>>::new();
+    let mut band_hits = 0usize;
+
+    for (i, sale) in sales.iter().enumerate() {
+        log::info!("Backtesting sale {}/{}", i + 1, sales.len());
+        let ts = NaiveDateTime::from_timestamp((sale.timestamp - 1) as i64, 0);
+
+        // the hedonic model may only know the sales before the one being priced
+        refit_hedonic_model(conn, collection_slug, &ts).await?;
+        let ctx =
+            PricingContext::make(conn, &collection, Some(sale.token_id), None, None, &ts).await?;
+        if ctx.token_traits.is_empty() {
+            continue;
+        }
+        let actual = from_wei(sale.price);
+
+        let prices = run_strategies(conn, &strategies, &ctx).await?;
+        for (name, price) in &prices {
+            if let Some(p) = price.filter(|p| *p > 0f64) {
+                samples.entry(name.clone()).or_default().push((p, actual));
+            }
+        }
+
+        let enabled = prices
+            .into_iter()
+            .filter(|(name, _)| {
+                collection.pricing_strategies.is_empty()
+                    || collection.pricing_strategies.contains(name)
+            })
+            .collect::<HashMap<_, _>>();
+        let (min_price, max_price, avg_price) = get_price_range(&enabled, ctx.collection_floor);
+
+        if avg_price > 0f64 {
+            samples
+                .entry(PRICE_PROFILE.to_string())
+                .or_default()
+                .push((avg_price, actual));
+            if actual >= min_price && actual <= max_price {
+                band_hits += 1;
+            }
+        }
+    }
+
+    let timestamp = Utc::now().timestamp() as i32;
+    let mut results = strategies
+        .iter()
+        .map(|s| s.name().to_string())
+        .chain(std::iter::once(PRICE_PROFILE.to_string()))
+        .map(|name| {
+            let s = samples.remove(&name).unwrap_or_default();
+            let (mae, mape) = get_errors(&s);
+            BacktestResult {
+                collection_slug: collection_slug.to_string(),
+                hit_rate: if name == PRICE_PROFILE && !s.is_empty() {
+                    Some(band_hits as f64 / s.len() as f64)
+                } else {
+                    None
+                },
+                strategy: name,
+                nr_sales: sales.len() as i32,
+                nr_priced: s.len() as i32,
+                mae,
+                mape,
+                timestamp,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    results.sort_by(|a, b| {
+        a.mape
+            .unwrap_or(f64::MAX)
+            .partial_cmp(&b.mape.unwrap_or(f64::MAX))
+            .unwrap()
+    });
+
+    Ok(results)
+}
+
+/// Mean absolute error and mean absolute percentage error of (estimate, actual) pairs
+pub fn get_errors(samples: &[(f64, f64)]) -> (Option<f64>, Option<f64>) {
+    if samples.is_empty() {
+        return (None, None);
+    }
+    let n = samples.len() as f64;
+    let mae = samples.iter().map(|(e, a)| (e - a).abs()).sum::<f64>() / n;
+    let pct_errors = samples
+        .iter()
+        .filter(|(_, a)| *a > 0f64)
+        .map(|(e, a)| (e - a).abs() / a)
+        .collect::<Vec<_>>();
+    let mape = match pct_errors.len() {
+        0 => None,
+        k => Some(pct_errors.iter().sum::<f64>() / k as f64),
+    };
+
+    (Some(mae), mape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_errors() {
+        assert_eq!(get_errors(&[]), (None, None));
+
+        let (mae, mape) = get_errors(&[(1.5, 1.0), (1.0, 2.0)]);
+        assert!((mae.unwrap() - 0.75).abs() < 1e-9);
+        assert!((mape.unwrap() - 0.5).abs() < 1e-9);
+
+        // free mints have no percentage error and don't count towards the mape
+        let (mae, mape) = get_errors(&[(1.5, 1.0), (0.5, 0.0)]);
+        assert!((mae.unwrap() - 0.5).abs() < 1e-9);
+        assert!((mape.unwrap() - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,123 @@
+use super::TraitRarities;
+use crate::from_wei;
+use crate::storage::read::read_open_bids_at_ts;
+use crate::storage::Bid;
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime};
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// How long a bid without an expiry is considered open
+static BID_MAX_AGE_DAYS: i64 = 7;
+
+/// Highest open bid for the token, including bids on its traits and collection bids.
+/// Without a token id only trait and collection bids count
+pub async fn get_top_bid(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_id: Option<i32>,
+    token_traits: &[TraitRarities],
+    ts: &NaiveDateTime,
+) -> Result<Option<f64>> {
+    let trait_ids = token_traits
+        .iter()
+        .map(|t| t.trait_id.clone())
+        .collect::<Vec<_>>();
+    let bids =
+        read_open_bids_at_ts(conn, collection_slug, ts, &Duration::days(BID_MAX_AGE_DAYS)).await?;
+
+    Ok(get_top_applying_bid(&bids, token_id, &trait_ids).map(from_wei))
+}
+
+/// Highest open bid for every trait that has one, collection bids included
+pub async fn get_trait_top_bids(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_traits: &[TraitRarities],
+    ts: &NaiveDateTime,
+) -> Result<HashMap<String, f64>> {
+    let bids =
+        read_open_bids_at_ts(conn, collection_slug, ts, &Duration::days(BID_MAX_AGE_DAYS)).await?;
+
+    Ok(token_traits
+        .iter()
+        .filter_map(|t| {
+            get_top_applying_bid(&bids, None, std::slice::from_ref(&t.trait_id))
+                .map(|bid| (t.trait_id.clone(), from_wei(bid)))
+        })
+        .collect())
+}
+
+/// Highest price of the bids on the token or on any of the traits, collection bids apply to
+/// all of them
+fn get_top_applying_bid(bids: &[Bid], token_id: Option<i32>, trait_ids: &[String]) -> Option<f64> {
+    bids.iter()
+        .filter(|b| match (b.token_id, &b.trait_id) {
+            (Some(id), _) => Some(id) == token_id,
+            (None, Some(t)) => trait_ids.contains(t),
+            (None, None) => true,
+        })
+        .filter_map(|b| b.price)
+        .reduce(f64::max)
+}
+
+/// Raises the price range so that no price is below the top bid, returns (min, max, avg)
+pub fn get_price_range_above_bid(
+    (min_price, max_price, avg_price): (f64, f64, f64),
+    top_bid: Option<f64>,
+) -> (f64, f64, f64) {
+    let bid = top_bid.unwrap_or_default();
+    let min_price = min_price.max(bid);
+    let avg_price = avg_price.max(min_price);
+
+    (min_price, max_price.max(avg_price), avg_price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_price_range_above_bid() {
+        assert_eq!(
+            get_price_range_above_bid((1.0, 3.0, 2.0), None),
+            (1.0, 3.0, 2.0)
+        );
+        assert_eq!(
+            get_price_range_above_bid((1.0, 3.0, 2.0), Some(1.5)),
+            (1.5, 3.0, 2.0)
+        );
+        assert_eq!(
+            get_price_range_above_bid((1.0, 3.0, 2.0), Some(2.5)),
+            (2.5, 3.0, 2.5)
+        );
+    }
+
+    #[test]
+    fn test_get_top_applying_bid() {
+        let bid = |token_id: Option<i32>, trait_id: Option<&str>, price: f64| Bid {
+            collection_slug: "c".to_string(),
+            update_type: "bid_entered".to_string(),
+            token_id,
+            trait_id: trait_id.map(String::from),
+            bidder: "0x1".to_string(),
+            price: Some(price),
+            timestamp: 0,
+            expires_at: None,
+        };
+        let bids = [
+            bid(None, None, 1.0),
+            bid(None, Some("hat:blue"), 5.0),
+            bid(Some(2), None, 3.0),
+        ];
+        let red = ["hat:red".to_string()];
+        let blue = ["hat:blue".to_string()];
+
+        // a trait bid doesn't apply to a token without the trait
+        assert_eq!(get_top_applying_bid(&bids, Some(1), &red), Some(1.0));
+        assert_eq!(get_top_applying_bid(&bids, Some(1), &blue), Some(5.0));
+        assert_eq!(get_top_applying_bid(&bids, Some(2), &red), Some(3.0));
+        assert_eq!(get_top_applying_bid(&bids, None, &red), Some(1.0));
+        assert_eq!(get_top_applying_bid(&[], None, &red), None);
+    }
+}
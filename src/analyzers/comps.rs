@@ -0,0 +1,212 @@
+use super::sales::get_average_collection_sales_at_ts;
+use super::strategies::PricingContext;
+use crate::analyzers::fx::{Currency, EthRates};
+use crate::from_wei;
+use crate::storage::read::{
+    read_asset_overlaps, read_assets_with_traits, read_sales_for_tokens_between_ts,
+};
+use crate::storage::Collection;
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime};
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// Only sales this recent are comparable
+static COMPS_MAX_AGE_DAYS: i64 = 90;
+static MAX_COMPS: usize = 20;
+/// Age at which a comp weighs half as much as a sale happening now
+static COMPS_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Why a sale counts as comparable to the token
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompReason {
+    MostValuedTrait,
+    RarestTrait,
+    /// The sold token shares the largest trait combination any other token shares
+    TraitOverlap,
+}
+
+/// A recent sale of a comparable token
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct Comp {
+    pub token_id: i32,
+    pub sale_price: f64,
+    pub sale_time: NaiveDateTime,
+    /// Sale price moved along with the collection's average price since the sale
+    pub adjusted_price: f64,
+    pub reasons: Vec<CompReason>,
+    /// Share of the comp in `comps_price`, higher for more reasons and more recent sales
+    pub weight: f64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct Comps {
+    pub collection_slug: String,
+    pub token_id: i32,
+    /// Most recent first
+    pub comps: Vec<Comp>,
+    /// Weighted average of the adjusted comp prices
+    pub comps_price: Option<f64>,
+    pub currency: Currency,
+}
+
+impl Comps {
+    /// Converts every sale price at the rate of its sale and the prices adjusted to now at the
+    /// latest rate
+    pub fn in_currency(mut self, rates: &EthRates) -> Self {
+        for c in self.comps.iter_mut() {
+            c.sale_price *= rates.rate(Some(&c.sale_time));
+            c.adjusted_price *= rates.rate(None);
+        }
+        self.comps_price = self.comps_price.map(|p| p * rates.rate(None));
+        self.currency = rates.currency;
+        self
+    }
+}
+
+/// Comps of a stored token at `ts`, None if the token has no traits
+pub async fn get_token_comps(
+    conn: &mut PgConnection,
+    collection: &Collection,
+    token_id: i32,
+    ts: &NaiveDateTime,
+) -> Result<Option<Comps>> {
+    let ctx = PricingContext::make(conn, collection, Some(token_id), None, None, ts).await?;
+    if ctx.token_traits.is_empty() {
+        return Ok(None);
+    }
+    let comps = get_comps(conn, &ctx).await?;
+
+    Ok(Some(Comps {
+        collection_slug: collection.slug.clone(),
+        token_id,
+        comps_price: get_comps_price(&comps),
+        comps,
+        currency: Currency::Eth,
+    }))
+}
+
+/// Recent sales of tokens sharing the token's most valued trait, rarest trait or largest shared
+/// trait combination, none from after `ctx.ts`
+pub async fn get_comps(conn: &mut PgConnection, ctx: &PricingContext) -> Result<Vec<Comp>> {
+    let mut candidates = HashMap::<i32, Vec<CompReason>>::new();
+    let mut add = |token_ids: Vec<i32>, reason: CompReason| {
+        for id in token_ids.into_iter().filter(|id| Some(*id) != ctx.token_id) {
+            candidates.entry(id).or_default().push(reason);
+        }
+    };
+
+    if let Some(t) = &ctx.most_valuable_trait {
+        add(
+            read_assets_with_traits(conn, &ctx.collection_slug, vec![t.trait_id.clone()]).await?,
+            CompReason::MostValuedTrait,
+        );
+    }
+    add(
+        read_assets_with_traits(conn, &ctx.collection_slug, vec![ctx.rarest_trait.clone()]).await?,
+        CompReason::RarestTrait,
+    );
+    if let Some(id) = ctx.token_id {
+        // the largest combination size still shared with another token
+        let overlap = read_asset_overlaps(conn, &ctx.collection_slug, id)
+            .await?
+            .into_iter()
+            .rev()
+            .find(|o| o.overlapping_ids.iter().any(|o| *o != id));
+        if let Some(o) = overlap {
+            add(o.overlapping_ids, CompReason::TraitOverlap);
+        }
+    }
+
+    let token_ids = candidates.keys().copied().collect::<Vec<_>>();
+    let sales = read_sales_for_tokens_between_ts(
+        conn,
+        &ctx.collection_slug,
+        &token_ids,
+        &(ctx.ts - Duration::days(COMPS_MAX_AGE_DAYS)),
+        &ctx.ts,
+    )
+    .await?;
+
+    let avg_now =
+        match get_average_collection_sales_at_ts(conn, &ctx.collection_slug, &ctx.window, &ctx.ts)
+            .await?
+        {
+            Some(v) => v,
+            None => return Ok(vec![]),
+        };
+
+    let mut comps = vec![];
+    for sale in sales.into_iter().take(MAX_COMPS) {
+        let sale_time = NaiveDateTime::from_timestamp(sale.timestamp as i64, 0);
+        let avg_at_sale = match get_average_collection_sales_at_ts(
+            conn,
+            &ctx.collection_slug,
+            &ctx.window,
+            &sale_time,
+        )
+        .await?
+        {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let reasons = candidates[&sale.token_id].clone();
+        let age_days = (ctx.ts - sale_time).num_seconds() as f64 / 86400f64;
+        comps.push(Comp {
+            token_id: sale.token_id,
+            sale_price: from_wei(sale.price),
+            sale_time,
+            adjusted_price: from_wei(sale.price) / avg_at_sale * avg_now,
+            weight: get_comp_weight(reasons.len(), age_days),
+            reasons,
+        });
+    }
+
+    Ok(comps)
+}
+
+fn get_comp_weight(nr_reasons: usize, age_days: f64) -> f64 {
+    nr_reasons as f64 * 0.5f64.powf(age_days / COMPS_HALF_LIFE_DAYS)
+}
+
+/// Weighted average of the adjusted prices, None without comps
+pub fn get_comps_price(comps: &[Comp]) -> Option<f64> {
+    let total_weight = comps.iter().map(|c| c.weight).sum::<f64>();
+    if total_weight <= 0f64 {
+        return None;
+    }
+
+    Some(
+        comps
+            .iter()
+            .map(|c| c.adjusted_price * c.weight)
+            .sum::<f64>()
+            / total_weight,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_comps_price() {
+        let comp = |adjusted_price: f64, nr_reasons: usize, age_days: f64| Comp {
+            token_id: 1,
+            sale_price: adjusted_price,
+            sale_time: NaiveDateTime::from_timestamp(0, 0),
+            adjusted_price,
+            reasons: vec![CompReason::RarestTrait; nr_reasons],
+            weight: get_comp_weight(nr_reasons, age_days),
+        };
+
+        assert_eq!(get_comps_price(&[]), None);
+        // two reasons one half-life ago weigh as much as one reason now
+        assert_eq!(
+            get_comps_price(&[comp(1.0, 1, 0.0), comp(4.0, 2, COMPS_HALF_LIFE_DAYS)]),
+            Some(2.5)
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use super::listings::get_trait_nr_listed;
+use super::sales::get_trait_sales;
+use super::strategies::PricingContext;
+use anyhow::Result;
+use chrono::Duration;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// z-score of the 90th percentile, gives a two sided 80% interval
+static Z_80: f64 = 1.2816;
+
+/// Log standard deviation assumed when neither the sales nor the strategies give one, about a
+/// factor 2 either way at 80%
+static PRIOR_LOG_SD: f64 = 0.5;
+
+/// Sales older than this are not counted as evidence
+static EVIDENCE_WINDOW_DAYS: i64 = 60;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Default)]
+pub struct PriceConfidence {
+    /// 0 (no evidence) to 1 (plenty of recent, agreeing evidence)
+    pub score: f64,
+    /// 80% interval around `avg_price`
+    pub interval: (f64, f64),
+    pub nr_sales: usize,
+    pub nr_listings: usize,
+    pub data_age_days: Option<f64>,
+    /// Coefficient of variation between the strategy prices
+    pub strategy_spread: f64,
+}
+
+pub async fn get_price_confidence(
+    conn: &mut PgConnection,
+    ctx: &PricingContext,
+    strategies: &HashMap<String, Option<f64>>,
+    avg_price: f64,
+) -> Result<PriceConfidence> {
+    let prices = strategies
+        .values()
+        .flatten()
+        .copied()
+        .filter(|p| *p > 0f64)
+        .collect::<Vec<_>>();
+
+    // the most valued trait is the main driver of the price, fall back to the rarest trait
+    let evidence_trait = match &ctx.most_valuable_trait {
+        Some(t) => t.trait_id.clone(),
+        None => ctx.rarest_trait.clone(),
+    };
+
+    let window_start = ctx.ts - Duration::days(EVIDENCE_WINDOW_DAYS);
+    let mut sales = get_trait_sales(conn, &ctx.collection_slug, &evidence_trait, &ctx.ts)
+        .await?
+        .into_iter()
+        .filter(|s| s.time > window_start)
+        .collect::<Vec<_>>();
+    if let Some(s) = ctx.last_sale.clone() {
+        if s.time > window_start
+            && !sales
+                .iter()
+                .any(|t| t.token_id == s.token_id && t.time == s.time)
+        {
+            sales.push(s);
+        }
+    }
+
+    let nr_listings =
+        get_trait_nr_listed(conn, &ctx.collection_slug, &evidence_trait, &ctx.ts).await?;
+
+    let data_age_days = sales
+        .iter()
+        .map(|s| s.time)
+        .max()
+        .map(|t| (ctx.ts - t).num_seconds() as f64 / 86_400f64);
+
+    let strategy_spread = get_coefficient_of_variation(&prices);
+
+    let log_sd = get_combined_log_sd(&sales.iter().map(|s| s.price).collect::<Vec<_>>(), &prices);
+
+    Ok(PriceConfidence {
+        score: get_confidence_score(
+            sales.len(),
+            nr_listings,
+            data_age_days.unwrap_or(f64::INFINITY),
+            strategy_spread,
+        ),
+        interval: get_price_interval(avg_price, log_sd),
+        nr_sales: sales.len(),
+        nr_listings,
+        data_age_days,
+        strategy_spread,
+    })
+}
+
+/// Multiplies evidence volume, freshness and agreement, each in the range 0 to 1
+pub fn get_confidence_score(
+    nr_sales: usize,
+    nr_listings: usize,
+    data_age_days: f64,
+    strategy_spread: f64,
+) -> f64 {
+    // a sale is worth more than a listing, ~10 sales gives 63% of the full evidence score
+    let evidence = 1f64 - (-(nr_sales as f64 + 0.5 * nr_listings as f64) / 10f64).exp();
+    let freshness = (-data_age_days / 30f64).exp();
+    let agreement = 1f64 / (1f64 + strategy_spread);
+
+    evidence * freshness.max(0.1) * agreement
+}
+
+/// Log-normal interval, prices can't go below zero and errors are relative
+pub fn get_price_interval(avg_price: f64, log_sd: f64) -> (f64, f64) {
+    (
+        avg_price * (-Z_80 * log_sd).exp(),
+        avg_price * (Z_80 * log_sd).exp(),
+    )
+}
+
+/// Spread between the strategies plus the spread of the comparable sales themselves, the prior
+/// when there are too few of both to measure any spread
+fn get_combined_log_sd(sales: &[f64], strategy_prices: &[f64]) -> f64 {
+    match (get_log_sd(sales), get_log_sd(strategy_prices)) {
+        (None, None) => PRIOR_LOG_SD,
+        (sales_sd, strategies_sd) => (sales_sd.unwrap_or_default().powi(2)
+            + strategies_sd.unwrap_or_default().powi(2))
+        .sqrt(),
+    }
+}
+
+/// None for fewer than two prices
+fn get_log_sd(prices: &[f64]) -> Option<f64> {
+    let logs = prices
+        .iter()
+        .filter(|p| **p > 0f64)
+        .map(|p| p.ln())
+        .collect::<Vec<_>>();
+    if logs.len() < 2 {
+        return None;
+    }
+    let mean = logs.iter().sum::<f64>() / logs.len() as f64;
+    Some((logs.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / (logs.len() - 1) as f64).sqrt())
+}
+
+fn get_coefficient_of_variation(prices: &[f64]) -> f64 {
+    if prices.len() < 2 {
+        return 0f64;
+    }
+    let mean = prices.iter().sum::<f64>() / prices.len() as f64;
+    let sd =
+        (prices.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (prices.len() - 1) as f64).sqrt();
+    sd / mean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_confidence_score() {
+        assert_eq!(get_confidence_score(0, 0, f64::INFINITY, 0f64), 0f64);
+
+        let strong = get_confidence_score(20, 10, 1f64, 0.1);
+        let stale = get_confidence_score(20, 10, 90f64, 0.1);
+        let spread = get_confidence_score(20, 10, 1f64, 1f64);
+        assert!(strong > stale && strong > spread && strong < 1f64);
+    }
+
+    #[test]
+    fn test_get_price_interval() {
+        assert_eq!(get_price_interval(2f64, 0f64), (2f64, 2f64));
+
+        let (low, high) = get_price_interval(2f64, get_combined_log_sd(&[1f64, 2f64, 4f64], &[]));
+        assert!(low < 2f64 && high > 2f64);
+        assert!((low * high - 4f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_get_price_interval_without_spread() {
+        // one sale and one strategy price say nothing about the spread, so the interval is wide
+        let (low, high) = get_price_interval(2f64, get_combined_log_sd(&[2f64], &[2f64]));
+        assert!(low < 1.2f64 && high > 3.5f64);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::analyzers::fx::Currency;
+use crate::custom::read_custom_price;
+use crate::from_wei;
+use crate::profiles::price_profile::PriceProfile;
+use crate::storage::read::{
+    read_assets_with_traits, read_collection, read_listed_for_collection_at_ts,
+};
+use crate::storage::Collection;
+use anyhow::Result;
+use cached::proc_macro::cached;
+use chrono::{NaiveDateTime, Utc};
+use futures::StreamExt;
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+/// A live listing next to the fair value estimate of the listed token
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct Deal {
+    pub token_id: i32,
+    pub listing_price: f64,
+    pub listed_at: NaiveDateTime,
+    pub min_price: f64,
+    pub avg_price: f64,
+    pub confidence: f64,
+    /// Listing price divided by `min_price`, below 1 the token is listed under its most
+    /// conservative estimate
+    pub price_to_min: f64,
+    pub price_to_avg: f64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DealFilter {
+    /// Only tokens having this trait
+    pub trait_id: Option<String>,
+    /// Highest listing price in ETH
+    pub max_price: Option<f64>,
+    pub min_confidence: Option<f64>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct Deals {
+    pub collection_slug: String,
+    pub deals: Vec<Deal>,
+    pub currency: Currency,
+}
+
+impl Deals {
+    /// Converts the listing prices and the estimates they are compared with to `currency`, the
+    /// ratios between them don't change
+    pub fn in_currency(mut self, currency: Currency, rate: f64) -> Self {
+        for d in self.deals.iter_mut() {
+            d.listing_price *= rate;
+            d.min_price *= rate;
+            d.avg_price *= rate;
+        }
+        self.currency = currency;
+        self
+    }
+}
+
+/// Currently listed tokens of the collection, cheapest relative to `min_price` first
+pub async fn get_deals(pool: PgPool, collection_slug: &str, filter: &DealFilter) -> Result<Deals> {
+    let mut conn = pool.acquire().await?;
+    let collection = read_collection(&mut conn, collection_slug).await?;
+
+    let mut listings =
+        read_listed_for_collection_at_ts(&mut conn, collection_slug, &Utc::now().naive_utc())
+            .await?;
+
+    if let Some(trait_id) = &filter.trait_id {
+        let token_ids =
+            read_assets_with_traits(&mut conn, collection_slug, vec![trait_id.to_lowercase()])
+                .await?
+                .into_iter()
+                .collect::<HashSet<_>>();
+        listings.retain(|l| token_ids.contains(&l.token_id));
+    }
+    if let Some(max_price) = filter.max_price {
+        listings.retain(|l| l.price.map(from_wei).unwrap_or(f64::MAX) <= max_price);
+    }
+
+    let mut stream = futures::stream::iter(listings)
+        .map(|l| {
+            let pool = pool.clone();
+            let collection = &collection;
+            async move {
+                let profile = _get_price_profile(pool, collection, l.token_id).await?;
+                Ok::<_, anyhow::Error>(profile.map(|p| (l, p)))
+            }
+        })
+        .buffer_unordered(6);
+
+    let mut deals = vec![];
+    while let Some(result) = stream.next().await {
+        let (listing, profile) = match result {
+            Ok(Some(r)) => r,
+            Ok(None) => continue,
+            Err(e) => {
+                log::info!("Error pricing listed token: {}", e);
+                continue;
+            }
+        };
+
+        if profile.min_price <= 0f64 || profile.avg_price <= 0f64 {
+            continue;
+        }
+        if profile.confidence.score < filter.min_confidence.unwrap_or_default() {
+            continue;
+        }
+
+        let listing_price = from_wei(listing.price.unwrap_or_default());
+        deals.push(Deal {
+            token_id: listing.token_id,
+            listing_price,
+            listed_at: NaiveDateTime::from_timestamp(listing.timestamp as i64, 0),
+            min_price: profile.min_price,
+            avg_price: profile.avg_price,
+            confidence: profile.confidence.score,
+            price_to_min: listing_price / profile.min_price,
+            price_to_avg: listing_price / profile.avg_price,
+        });
+    }
+
+    deals.sort_by(|a, b| a.price_to_min.partial_cmp(&b.price_to_min).unwrap());
+
+    Ok(Deals {
+        collection_slug: collection_slug.to_string(),
+        deals,
+        currency: Currency::Eth,
+    })
+}
+
+#[cached(
+    size = 10_000,
+    time = 3600,
+    result = true,
+    key = "String",
+    convert = r#"{ format!("{}:{}", collection.slug, token_id) }"#
+)]
+async fn _get_price_profile(
+    pool: PgPool,
+    collection: &Collection,
+    token_id: i32,
+) -> Result<Option<PriceProfile>> {
+    if let Some(price) = read_custom_price(&collection.slug, token_id)? {
+        return Ok(Some(PriceProfile::from_custom_price(price, collection)));
+    }
+
+    let mut conn = pool.acquire().await?;
+    PriceProfile::make_for_token(&mut conn, collection, token_id, None).await
+}
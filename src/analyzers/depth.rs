@@ -0,0 +1,182 @@
+use crate::analyzers::fx::Currency;
+use crate::from_wei;
+use crate::storage::read::{read_assets_with_traits, read_listed_for_collection_at_ts};
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::PgConnection;
+use std::collections::HashSet;
+
+/// Listings available up to and including `price`
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, PartialEq)]
+pub struct DepthLevel {
+    pub price: f64,
+    pub nr_listed: usize,
+    pub total_cost: f64,
+}
+
+/// Outcome of buying the cheapest listings
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, PartialEq)]
+pub struct Sweep {
+    pub nr_requested: usize,
+    /// Less than `nr_requested` if there are not enough listings
+    pub nr_bought: usize,
+    pub total_cost: f64,
+    pub avg_price: f64,
+    /// Price of the most expensive token bought
+    pub max_price: f64,
+    pub floor_before: Option<f64>,
+    /// None if the sweep bought every listing
+    pub floor_after: Option<f64>,
+    /// Relative change of the floor, 0.1 is a 10% increase
+    pub floor_change: Option<f64>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct Depth {
+    pub collection_slug: String,
+    pub trait_id: Option<String>,
+    pub floor_price: Option<f64>,
+    pub nr_listed: usize,
+    /// Cumulative listings per price level, cheapest first
+    pub levels: Vec<DepthLevel>,
+    pub sweep: Option<Sweep>,
+    pub currency: Currency,
+}
+
+impl Depth {
+    /// Converts the floor, the cost of every level and the sweep to `currency`, the counts of
+    /// listed and bought tokens stay as they are
+    pub fn in_currency(mut self, currency: Currency, rate: f64) -> Self {
+        self.floor_price = self.floor_price.map(|p| p * rate);
+        for l in self.levels.iter_mut() {
+            l.price *= rate;
+            l.total_cost *= rate;
+        }
+        if let Some(s) = self.sweep.as_mut() {
+            s.total_cost *= rate;
+            s.avg_price *= rate;
+            s.max_price *= rate;
+            s.floor_before = s.floor_before.map(|p| p * rate);
+            s.floor_after = s.floor_after.map(|p| p * rate);
+        }
+        self.currency = currency;
+        self
+    }
+}
+
+/// Listing depth of the collection at `ts`, only tokens having `trait_id` if set, and the
+/// outcome of sweeping the `sweep` cheapest of them
+pub async fn get_depth(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    trait_id: Option<&str>,
+    sweep: Option<usize>,
+    ts: &NaiveDateTime,
+) -> Result<Depth> {
+    let mut listings = read_listed_for_collection_at_ts(conn, collection_slug, ts).await?;
+
+    if let Some(trait_id) = trait_id {
+        let token_ids =
+            read_assets_with_traits(conn, collection_slug, vec![trait_id.to_lowercase()])
+                .await?
+                .into_iter()
+                .collect::<HashSet<_>>();
+        listings.retain(|l| token_ids.contains(&l.token_id));
+    }
+
+    let mut prices = listings
+        .into_iter()
+        .filter_map(|l| l.price.map(from_wei))
+        .collect::<Vec<_>>();
+    prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    Ok(Depth {
+        collection_slug: collection_slug.to_string(),
+        trait_id: trait_id.map(String::from),
+        floor_price: prices.first().copied(),
+        nr_listed: prices.len(),
+        levels: get_depth_levels(&prices),
+        sweep: sweep.map(|n| simulate_sweep(&prices, n)),
+        currency: Currency::Eth,
+    })
+}
+
+/// `prices` sorted cheapest first
+fn get_depth_levels(prices: &[f64]) -> Vec<DepthLevel> {
+    let mut levels: Vec<DepthLevel> = vec![];
+    let mut total_cost = 0f64;
+    for (i, price) in prices.iter().enumerate() {
+        total_cost += price;
+        let level = DepthLevel {
+            price: *price,
+            nr_listed: i + 1,
+            total_cost,
+        };
+        match levels.last_mut() {
+            Some(last) if last.price == *price => *last = level,
+            _ => levels.push(level),
+        }
+    }
+    levels
+}
+
+/// `prices` sorted cheapest first
+fn simulate_sweep(prices: &[f64], nr_requested: usize) -> Sweep {
+    let bought = &prices[..nr_requested.min(prices.len())];
+    let total_cost = bought.iter().sum::<f64>();
+    let floor_before = prices.first().copied();
+    let floor_after = prices.get(bought.len()).copied();
+
+    Sweep {
+        nr_requested,
+        nr_bought: bought.len(),
+        total_cost,
+        avg_price: if bought.is_empty() {
+            0f64
+        } else {
+            total_cost / bought.len() as f64
+        },
+        max_price: bought.last().copied().unwrap_or_default(),
+        floor_before,
+        floor_after,
+        floor_change: match (floor_before, floor_after) {
+            (Some(before), Some(after)) if before > 0f64 => Some(after / before - 1f64),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth_and_sweep() {
+        let prices = vec![1.0, 1.0, 1.5, 2.0, 4.0];
+
+        let levels = get_depth_levels(&prices);
+        assert_eq!(levels.len(), 4);
+        assert_eq!(
+            levels[0],
+            DepthLevel {
+                price: 1.0,
+                nr_listed: 2,
+                total_cost: 2.0
+            }
+        );
+        assert_eq!(levels[3].nr_listed, 5);
+        assert_eq!(levels[3].total_cost, 9.5);
+
+        let sweep = simulate_sweep(&prices, 3);
+        assert_eq!(sweep.nr_bought, 3);
+        assert_eq!(sweep.total_cost, 3.5);
+        assert_eq!(sweep.max_price, 1.5);
+        assert_eq!(sweep.floor_after, Some(2.0));
+        assert_eq!(sweep.floor_change, Some(1.0));
+
+        let sweep = simulate_sweep(&prices, 10);
+        assert_eq!(sweep.nr_bought, 5);
+        assert_eq!(sweep.floor_after, None);
+        assert_eq!(sweep.floor_change, None);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::storage::Collection;
+
+/// What the seller pays out of a sale at `avg_price`
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Default, PartialEq)]
+pub struct FeeBreakdown {
+    pub marketplace_fee_basis_points: i32,
+    pub royalty_basis_points: i32,
+    pub total_fee_basis_points: i32,
+    /// Part of the total that is neither marketplace fee nor royalty
+    pub other_fee_basis_points: i32,
+    pub marketplace_fee: f64,
+    pub royalty: f64,
+    pub other_fee: f64,
+}
+
+impl FeeBreakdown {
+    pub fn make(collection: &Collection, price: f64) -> Self {
+        let marketplace = collection.opensea_seller_fee_basis_points;
+        let royalty = collection.dev_seller_fee_basis_points;
+        let total = collection
+            .seller_fee_basis_points
+            .max(marketplace + royalty);
+        let other = total - marketplace - royalty;
+
+        Self {
+            marketplace_fee_basis_points: marketplace,
+            royalty_basis_points: royalty,
+            total_fee_basis_points: total,
+            other_fee_basis_points: other,
+            marketplace_fee: get_fee(price, marketplace),
+            royalty: get_fee(price, royalty),
+            other_fee: get_fee(price, other),
+        }
+    }
+
+    /// What the seller receives of a sale at `price`
+    pub fn net_price(&self, price: f64) -> f64 {
+        price - get_fee(price, self.total_fee_basis_points)
+    }
+
+    /// Scales the fees by `rate`, the breakdown is converted along with the profile holding it
+    pub fn in_currency(mut self, rate: f64) -> Self {
+        self.marketplace_fee *= rate;
+        self.royalty *= rate;
+        self.other_fee *= rate;
+        self
+    }
+}
+
+fn get_fee(price: f64, basis_points: i32) -> f64 {
+    price * basis_points as f64 / 10_000f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_breakdown() {
+        let collection = |seller_fee_basis_points: i32| Collection {
+            slug: "test".to_string(),
+            name: "Test".to_string(),
+            address: "0x0".to_string(),
+            total_supply: 1,
+            floor_price: 0f64,
+            rarity_cutoff: 0f64,
+            ignored_trait_types_rarity: vec![],
+            ignored_trait_types_overlap: vec![],
+            overlap_sizes: vec![],
+            pricing_strategies: vec![],
+            include_flagged_sales: false,
+            analysis_window_days: None,
+            owners_synced_at: None,
+            seller_fee_basis_points,
+            opensea_seller_fee_basis_points: 250,
+            dev_seller_fee_basis_points: 500,
+            banner_image_url: String::new(),
+            daily_volume: 0f64,
+            daily_sales: 0f64,
+            daily_avg_price: 0f64,
+            weekly_avg_price: 0f64,
+            monthly_avg_price: 0f64,
+            nr_owners: 0f64,
+            avg_trait_rarity: 0f64,
+        };
+
+        let fees = FeeBreakdown::make(&collection(0), 2.0);
+
+        assert_eq!(fees.total_fee_basis_points, 750);
+        assert_eq!(fees.other_fee_basis_points, 0);
+        assert_eq!(fees.marketplace_fee, 0.05);
+        assert_eq!(fees.royalty, 0.1);
+        assert_eq!(fees.net_price(2.0), 1.85);
+
+        // fees the marketplace and royalty don't account for are reported on their own
+        let fees = FeeBreakdown::make(&collection(1000), 2.0);
+
+        assert_eq!(fees.total_fee_basis_points, 1000);
+        assert_eq!(fees.other_fee_basis_points, 250);
+        assert_eq!(fees.other_fee, 0.05);
+        assert_eq!(fees.net_price(2.0), 1.8);
+    }
+}
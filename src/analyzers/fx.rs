@@ -0,0 +1,97 @@
+use crate::storage::read::{read_fx_rate_at_ts, read_fx_rates, read_latest_fx_rate};
+use crate::storage::FxRate;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::PgConnection;
+
+#[derive(
+    Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Copy, PartialEq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Currency {
+    #[default]
+    Eth,
+    Usd,
+}
+
+/// Price of one ETH in `currency`, at `ts` for historical figures or the latest rate if None
+pub async fn get_eth_rate(
+    conn: &mut PgConnection,
+    currency: Currency,
+    ts: Option<&NaiveDateTime>,
+) -> Result<f64> {
+    let rate = match currency {
+        Currency::Eth => return Ok(1f64),
+        Currency::Usd => match ts {
+            Some(ts) => read_fx_rate_at_ts(conn, ts).await?,
+            None => read_latest_fx_rate(conn).await?,
+        },
+    };
+
+    rate.map(|r| r.eth_usd)
+        .ok_or_else(|| anyhow!("no ETH/USD rate stored"))
+}
+
+/// All stored rates of a currency, for converting many past figures at the rate of their time
+/// without a query each
+pub struct EthRates {
+    pub currency: Currency,
+    rates: Vec<FxRate>,
+}
+
+impl EthRates {
+    pub async fn load(conn: &mut PgConnection, currency: Currency) -> Result<Self> {
+        let rates = match currency {
+            Currency::Eth => vec![],
+            Currency::Usd => read_fx_rates(conn).await?,
+        };
+        if currency != Currency::Eth && rates.is_empty() {
+            return Err(anyhow!("no ETH/USD rate stored"));
+        }
+        Ok(Self { currency, rates })
+    }
+
+    /// Same rate as `get_eth_rate`: at `ts` the last one before it, else the first one after,
+    /// the latest rate if None
+    pub fn rate(&self, ts: Option<&NaiveDateTime>) -> f64 {
+        if self.currency == Currency::Eth {
+            return 1f64;
+        }
+        let after = match ts {
+            Some(ts) => self
+                .rates
+                .partition_point(|r| r.timestamp as i64 <= ts.timestamp()),
+            None => self.rates.len(),
+        };
+        self.rates[after.saturating_sub(1)].eth_usd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eth_rates() {
+        let rates = EthRates {
+            currency: Currency::Usd,
+            rates: vec![
+                FxRate {
+                    timestamp: 100,
+                    eth_usd: 2000.0,
+                },
+                FxRate {
+                    timestamp: 200,
+                    eth_usd: 3000.0,
+                },
+            ],
+        };
+        let at = |ts: i64| rates.rate(Some(&NaiveDateTime::from_timestamp(ts, 0)));
+
+        assert_eq!(at(50), 2000.0);
+        assert_eq!(at(100), 2000.0);
+        assert_eq!(at(199), 2000.0);
+        assert_eq!(at(250), 3000.0);
+        assert_eq!(rates.rate(None), 3000.0);
+    }
+}
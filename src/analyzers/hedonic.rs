@@ -0,0 +1,368 @@
+use super::sales::{get_average_collection_sales_at_ts, AVG_PRICE_WINDOW_DAYS};
+use super::window::Window;
+use super::*;
+use crate::from_wei;
+use crate::storage::read::*;
+use crate::storage::write::write_hedonic_model;
+use crate::storage::{HedonicModel, SaleEvent, TraitPremium};
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime};
+use sqlx::PgConnection;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+/// Minimum number of usable sales before a model is fitted
+pub static MIN_SALES: usize = 30;
+
+/// Strength of the ridge penalty on the trait coefficients
+pub static RIDGE_LAMBDA: f64 = 1.0;
+
+/// Age after which a new model version is fitted
+pub static REFIT_DAYS: i64 = 1;
+
+/// Window of the market term, fitting and pricing must use the same one so a `?window=` override
+/// does not apply here
+async fn get_market_window(conn: &mut PgConnection, collection_slug: &str) -> Result<Window> {
+    let collection = read_collection(conn, collection_slug).await?;
+    Ok(Window::resolve(None, &collection, AVG_PRICE_WINDOW_DAYS))
+}
+
+/// Fits `ln(price) = intercept + market * ln(collection avg) + sum(trait premiums)` over the
+/// stored sales of the collection before `ts` and stores it as the model version at `ts`
+pub async fn fit_hedonic_model(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    ts: &NaiveDateTime,
+) -> Result<Option<HedonicModel>> {
+    let asset_traits = read_asset_traits_for_collection(conn, collection_slug).await?;
+    let market_window = Duration::days(get_market_window(conn, collection_slug).await?.days);
+
+    let sales = read_sales_for_collection_after_ts(
+        conn,
+        collection_slug,
+        &NaiveDateTime::from_timestamp(0, 0),
+    )
+    .await?;
+
+    let (model, premiums) =
+        match fit_model(collection_slug, sales, &asset_traits, market_window, ts) {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+
+    write_hedonic_model(conn, &model, premiums).await?;
+
+    Ok(Some(model))
+}
+
+/// Fits a new model version at `ts` unless the latest one is less than `REFIT_DAYS` old
+pub async fn refit_hedonic_model(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    ts: &NaiveDateTime,
+) -> Result<()> {
+    let refit_before = (*ts - Duration::days(REFIT_DAYS)).timestamp();
+    match read_hedonic_model_at_ts(conn, collection_slug, ts).await? {
+        Some(m) if m.timestamp as i64 >= refit_before => {}
+        _ => {
+            fit_hedonic_model(conn, collection_slug, ts).await?;
+        }
+    }
+    Ok(())
+}
+
+fn fit_model(
+    collection_slug: &str,
+    mut sales: Vec<SaleEvent>,
+    asset_traits: &HashMap<i32, Vec<String>>,
+    market_window: Duration,
+    ts: &NaiveDateTime,
+) -> Option<(HedonicModel, Vec<TraitPremium>)> {
+    sales.retain(|s| (s.timestamp as i64) < ts.timestamp());
+    sales.sort_by_key(|s| s.timestamp);
+
+    let trait_ids = asset_traits
+        .values()
+        .flatten()
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let columns = trait_ids
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.clone(), i + 2))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut rows = vec![];
+    let mut y = vec![];
+    let mut window = VecDeque::<(i64, f64)>::new();
+    let mut window_sum = 0f64;
+    for sale in &sales {
+        let price = from_wei(sale.price);
+        let ts = sale.timestamp as i64;
+
+        while let Some((t, p)) = window.front() {
+            if *t > ts - market_window.num_seconds() {
+                break;
+            }
+            window_sum -= p;
+            window.pop_front();
+        }
+
+        if !window.is_empty() && price > 0f64 {
+            if let Some(traits) = asset_traits.get(&sale.token_id) {
+                let market = window_sum / window.len() as f64;
+                let mut row = vec![(0usize, 1f64), (1usize, market.ln())];
+                row.extend(traits.iter().map(|t| (columns[t], 1f64)));
+                rows.push(row);
+                y.push(price.ln());
+            }
+        }
+
+        window.push_back((ts, price));
+        window_sum += price;
+    }
+
+    if rows.len() < MIN_SALES {
+        return None;
+    }
+
+    let beta = fit_ridge(&rows, &y, columns.len() + 2, 2, RIDGE_LAMBDA)?;
+
+    let model = HedonicModel {
+        collection_slug: collection_slug.to_string(),
+        intercept: beta[0],
+        market_coefficient: beta[1],
+        r_squared: get_r_squared(&rows, &y, &beta),
+        nr_sales: rows.len() as i32,
+        timestamp: ts.timestamp() as i32,
+    };
+
+    let premiums = columns
+        .into_iter()
+        .map(|(trait_id, i)| TraitPremium {
+            collection_slug: collection_slug.to_string(),
+            trait_id,
+            premium: beta[i],
+            timestamp: model.timestamp,
+        })
+        .collect::<Vec<_>>();
+
+    Some((model, premiums))
+}
+
+/// Premium of every trait as a price multiplier, 1.2 means the trait adds 20%, according to
+/// the latest model fitted at or before `ts`
+pub async fn get_trait_premiums(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_traits: &[TraitRarities],
+    ts: &NaiveDateTime,
+) -> Result<HashMap<String, f64>> {
+    match read_hedonic_model_at_ts(conn, collection_slug, ts).await? {
+        Some(model) => get_model_premiums(conn, &model, token_traits).await,
+        None => Ok(HashMap::new()),
+    }
+}
+
+async fn get_model_premiums(
+    conn: &mut PgConnection,
+    model: &HedonicModel,
+    token_traits: &[TraitRarities],
+) -> Result<HashMap<String, f64>> {
+    let trait_ids = token_traits
+        .iter()
+        .map(|t| t.trait_id.clone())
+        .collect::<Vec<_>>();
+
+    Ok(read_trait_premiums(conn, model, &trait_ids)
+        .await?
+        .into_iter()
+        .map(|p| (p.trait_id, p.premium.exp()))
+        .collect())
+}
+
+/// Uses the latest model fitted at or before `ts`, so historical prices only know earlier sales
+pub async fn get_hedonic_price(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_traits: &[TraitRarities],
+    ts: &NaiveDateTime,
+) -> Result<Option<f64>> {
+    let model = match read_hedonic_model_at_ts(conn, collection_slug, ts).await? {
+        Some(m) => m,
+        None => return Ok(None),
+    };
+
+    let market_window = get_market_window(conn, collection_slug).await?;
+    let market = match get_average_collection_sales_at_ts(conn, collection_slug, &market_window, ts)
+        .await?
+    {
+        Some(m) if m > 0f64 => from_wei(m),
+        _ => return Ok(None),
+    };
+
+    let premiums = get_model_premiums(conn, &model, token_traits).await?;
+
+    let log_price = model.intercept
+        + model.market_coefficient * market.ln()
+        + premiums.values().map(|p| p.ln()).sum::<f64>();
+
+    Ok(Some(log_price.exp()))
+}
+
+/// Solves `(X'X + lambda * P) beta = X'y` where `P` penalizes only the columns from
+/// `penalized_from` onwards, rows are sparse lists of (column, value)
+pub fn fit_ridge(
+    rows: &[Vec<(usize, f64)>],
+    y: &[f64],
+    nr_columns: usize,
+    penalized_from: usize,
+    lambda: f64,
+) -> Option<Vec<f64>> {
+    let mut a = vec![vec![0f64; nr_columns]; nr_columns];
+    let mut b = vec![0f64; nr_columns];
+
+    for (row, y) in rows.iter().zip(y) {
+        for (i, xi) in row {
+            b[*i] += xi * y;
+            for (j, xj) in row {
+                a[*i][*j] += xi * xj;
+            }
+        }
+    }
+    for (i, a) in a.iter_mut().enumerate().skip(penalized_from) {
+        a[i] += lambda;
+    }
+
+    solve(a, b)
+}
+
+/// Gaussian elimination with partial pivoting, None if the system is singular
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot =
+            (col..n).max_by(|i, j| a[*i][col].abs().partial_cmp(&a[*j][col].abs()).unwrap())?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (top, bottom) = a.split_at_mut(col + 1);
+        let pivot_row = &top[col];
+        for (offset, row) in bottom.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            if factor == 0f64 {
+                continue;
+            }
+            for (x, p) in row.iter_mut().zip(pivot_row).skip(col) {
+                *x -= factor * p;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0f64; n];
+    for row in (0..n).rev() {
+        let sum = (row + 1..n).map(|k| a[row][k] * x[k]).sum::<f64>();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+fn get_r_squared(rows: &[Vec<(usize, f64)>], y: &[f64], beta: &[f64]) -> f64 {
+    let mean = y.iter().sum::<f64>() / y.len() as f64;
+    let ss_tot = y.iter().map(|v| (v - mean).powi(2)).sum::<f64>();
+    let ss_res = rows
+        .iter()
+        .zip(y)
+        .map(|(row, v)| (v - row.iter().map(|(i, x)| beta[*i] * x).sum::<f64>()).powi(2))
+        .sum::<f64>();
+
+    if ss_tot == 0f64 {
+        0f64
+    } else {
+        1f64 - ss_res / ss_tot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_ridge() {
+        // y = 1 + 2 * market + 0.5 * trait
+        let rows = (0..20)
+            .map(|i| {
+                let mut row = vec![(0, 1f64), (1, i as f64 / 10f64)];
+                if i % 2 == 0 {
+                    row.push((2, 1f64));
+                }
+                row
+            })
+            .collect::<Vec<_>>();
+        let y = rows
+            .iter()
+            .map(|r| r.iter().map(|(i, x)| [1f64, 2f64, 0.5][*i] * x).sum())
+            .collect::<Vec<f64>>();
+
+        let beta = fit_ridge(&rows, &y, 3, 2, 0f64).unwrap();
+        assert!((beta[0] - 1f64).abs() < 1e-9);
+        assert!((beta[1] - 2f64).abs() < 1e-9);
+        assert!((beta[2] - 0.5).abs() < 1e-9);
+        assert!((get_r_squared(&rows, &y, &beta) - 1f64).abs() < 1e-9);
+
+        // the penalty shrinks the trait premium towards zero
+        let beta = fit_ridge(&rows, &y, 3, 2, 10f64).unwrap();
+        assert!(beta[2] < 0.5 && beta[2] > 0f64);
+    }
+
+    #[test]
+    fn test_fit_model_ignores_later_sales() {
+        let asset_traits = (0..40)
+            .map(|id| (id, vec![if id % 2 == 0 { "a" } else { "b" }.to_string()]))
+            .collect::<HashMap<_, _>>();
+        let sale = |token_id: i32, price: f64| SaleEvent {
+            collection_slug: "test".to_string(),
+            token_id,
+            timestamp: token_id * 3600,
+            price: price * 1e18,
+            buyer: None,
+            seller: None,
+            flag: None,
+            payment_token: "ETH".to_string(),
+            payment_amount: price,
+            payment_decimals: 18,
+        };
+        let sales = || {
+            (0..40)
+                .map(|id| sale(id, 1f64 + (id % 3) as f64 / 10f64 + (id % 2) as f64 / 5f64))
+                .collect::<Vec<_>>()
+        };
+        let ts = NaiveDateTime::from_timestamp(40 * 3600, 0);
+
+        let (model, premiums) =
+            fit_model("test", sales(), &asset_traits, Duration::days(1), &ts).unwrap();
+
+        let mut with_later = sales();
+        with_later.push(sale(41, 100f64));
+        let (later_model, later_premiums) =
+            fit_model("test", with_later, &asset_traits, Duration::days(1), &ts).unwrap();
+
+        assert_eq!(model.intercept, later_model.intercept);
+        assert_eq!(model.market_coefficient, later_model.market_coefficient);
+        assert_eq!(model.nr_sales, 39);
+        assert_eq!(
+            premiums.iter().map(|p| p.premium).collect::<Vec<_>>(),
+            later_premiums.iter().map(|p| p.premium).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_solve_singular() {
+        assert!(solve(vec![vec![1f64, 1f64], vec![1f64, 1f64]], vec![1f64, 1f64]).is_none());
+    }
+}
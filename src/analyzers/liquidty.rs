@@ -1,8 +1,7 @@
 use super::sales::*;
-use crate::analyzers::rarities::*;
+use super::TraitRarities;
 use anyhow::Result;
-use chrono::prelude::Utc;
-use chrono::Duration;
+use chrono::{Duration, NaiveDateTime};
 use sqlx::PgConnection;
 
 pub async fn get_sale_count_trait(
@@ -10,10 +9,11 @@ pub async fn get_sale_count_trait(
     collection_slug: &str,
     trait_name: &str,
     days_back: usize,
+    ts: &NaiveDateTime,
 ) -> Result<usize> {
-    let days_ago = Utc::now().naive_utc() - Duration::days(days_back as i64);
+    let days_ago = *ts - Duration::days(days_back as i64);
 
-    let nr_sales = get_trait_sales(conn, collection_slug, trait_name)
+    let nr_sales = get_trait_sales(conn, collection_slug, trait_name, ts)
         .await
         .unwrap()
         .into_iter()
@@ -26,14 +26,14 @@ pub async fn get_sale_count_trait(
 pub async fn get_lowest_sale_count(
     conn: &mut PgConnection,
     collection_slug: &str,
-    token_id: i32,
+    token_traits: &[TraitRarities],
     days_back: usize,
+    ts: &NaiveDateTime,
 ) -> Result<(String, usize)> {
-    let token_traits = get_trait_rarities(conn, collection_slug, token_id).await?;
-
     let mut lowest_frequency = (String::default(), usize::MAX);
     for t in token_traits {
-        let frequency = get_sale_count_trait(conn, collection_slug, &t.trait_id, days_back).await?;
+        let frequency =
+            get_sale_count_trait(conn, collection_slug, &t.trait_id, days_back, ts).await?;
         if frequency < lowest_frequency.1 {
             lowest_frequency.1 = frequency;
             lowest_frequency.0 = t.trait_id.clone();
@@ -45,14 +45,14 @@ pub async fn get_lowest_sale_count(
 pub async fn get_avg_sale_count(
     conn: &mut PgConnection,
     collection_slug: &str,
-    token_id: i32,
+    token_traits: &[TraitRarities],
     days_back: usize,
+    ts: &NaiveDateTime,
 ) -> Result<f64> {
-    let token_traits = get_trait_rarities(conn, collection_slug, token_id).await?;
-
     let mut cumulative_frequency = 0f64;
-    for t in &token_traits {
-        let frequency = get_sale_count_trait(conn, collection_slug, &t.trait_id, days_back).await?;
+    for t in token_traits {
+        let frequency =
+            get_sale_count_trait(conn, collection_slug, &t.trait_id, days_back, ts).await?;
         cumulative_frequency += frequency as f64;
     }
     Ok(cumulative_frequency / token_traits.len() as f64)
@@ -2,20 +2,25 @@ use super::*;
 use crate::from_wei;
 use crate::storage::read::*;
 use anyhow::Result;
+use chrono::NaiveDateTime;
 use sqlx::PgConnection;
 
 pub async fn get_token_listings(
     conn: &mut PgConnection,
     collection_slug: &str,
     token_ids: Vec<i32>,
+    ts: &NaiveDateTime,
 ) -> Result<Vec<TokenListing>> {
     let mut map: Vec<TokenListing> = Vec::new();
     for id in token_ids {
         let listing = read_latests_listing_for_asset(conn, collection_slug, id).await?;
-        if !listing.is_empty() {
+        if let Some(l) = listing
+            .iter()
+            .find(|l| (l.timestamp as i64) < ts.timestamp())
+        {
             map.push(TokenListing {
                 token_id: id,
-                price: listing[0].price,
+                price: l.price,
             });
         }
     }
@@ -26,37 +31,33 @@ pub async fn get_trait_listings(
     conn: &mut PgConnection,
     collection_slug: &str,
     trait_name: &str,
+    ts: &NaiveDateTime,
 ) -> Result<Vec<TraitListing>> {
-    let assets_with_trait = read_assets_with_trait(conn, collection_slug, trait_name)
-        .await
-        .unwrap();
+    let listings =
+        read_trait_listings_at_ts(conn, collection_slug, trait_name, ts.timestamp() as i32).await?;
 
-    let ids: Vec<_> = assets_with_trait.into_iter().map(|a| a.token_id).collect();
-
-    let mut all_assets: Vec<_> = get_token_listings(conn, collection_slug, ids).await?;
-
-    all_assets = all_assets
+    let mut all_assets = listings
         .into_iter()
-        .filter(|a| a.price.is_some())
+        .filter_map(|l| {
+            l.price.map(|p| TraitListing {
+                token_id: l.token_id,
+                price: from_wei(p),
+            })
+        })
         .collect::<Vec<_>>();
 
-    all_assets.sort_by(|a, b| a.price.unwrap().partial_cmp(&b.price.unwrap()).unwrap());
+    all_assets.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
 
-    Ok(all_assets
-        .into_iter()
-        .map(|t| TraitListing {
-            token_id: t.token_id,
-            price: from_wei(t.price.unwrap()),
-        })
-        .collect())
+    Ok(all_assets)
 }
 
 pub async fn get_trait_nr_listed(
     conn: &mut PgConnection,
     collection_slug: &str,
     trait_name: &str,
+    ts: &NaiveDateTime,
 ) -> Result<usize> {
-    Ok(get_trait_listings(conn, collection_slug, trait_name)
+    Ok(get_trait_listings(conn, collection_slug, trait_name, ts)
         .await?
         .len())
 }
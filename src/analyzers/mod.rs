@@ -1,9 +1,25 @@
+pub mod backtest;
+pub mod bids;
+pub mod comps;
+pub mod confidence;
+pub mod deals;
+pub mod depth;
+pub mod fees;
+pub mod fx;
+pub mod hedonic;
 pub mod liquidty;
 pub mod listings;
 pub mod prices;
 pub mod rarities;
+pub mod rarity_scores;
 pub mod sales;
+pub mod similar;
+pub mod strategies;
+pub mod time_to_sell;
+pub mod trait_index;
 pub mod wallet;
+pub mod wash_trades;
+pub mod window;
 
 use chrono::NaiveDateTime;
 #[derive(Default, Clone, Debug)]
@@ -1,18 +1,21 @@
 use anyhow::Result;
-use chrono::prelude::Utc;
+use chrono::NaiveDateTime;
 use sqlx::PgConnection;
 
 use super::listings::get_trait_listings;
 use super::sales::*;
+use super::window::Window;
 use super::*;
-use crate::storage::read::read_collection;
+use crate::from_wei;
+use crate::storage::read::{read_collection, read_listed_for_collection_at_ts};
 
 pub async fn get_trait_floor(
     conn: &mut PgConnection,
     collection_slug: &str,
     trait_name: &str,
+    ts: &NaiveDateTime,
 ) -> Result<Option<TraitFloor>> {
-    let listings = get_trait_listings(conn, collection_slug, trait_name).await?;
+    let listings = get_trait_listings(conn, collection_slug, trait_name, ts).await?;
 
     if listings.is_empty() {
         Ok(None)
@@ -31,11 +34,25 @@ pub async fn get_collection_floor(conn: &mut PgConnection, collection_slug: &str
     Ok(collection.floor_price)
 }
 
+pub async fn get_collection_floor_at_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    ts: &NaiveDateTime,
+) -> Result<Option<f64>> {
+    Ok(read_listed_for_collection_at_ts(conn, collection_slug, ts)
+        .await?
+        .into_iter()
+        .filter_map(|l| l.price)
+        .map(from_wei)
+        .reduce(f64::min))
+}
+
 pub async fn get_most_valued_trait_floor(
     conn: &mut PgConnection,
     collection_slug: &str,
     token_traits: Vec<TraitRarities>,
     cutoff: f64,
+    ts: &NaiveDateTime,
 ) -> Result<Option<TraitFloor>> {
     let mut token_traits_filtered = token_traits
         .iter()
@@ -50,7 +67,7 @@ pub async fn get_most_valued_trait_floor(
 
     let mut highest_floor = TraitFloor::default();
     for t in token_traits_filtered {
-        let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id).await?;
+        let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id, ts).await?;
         if !trait_listings.is_empty() && trait_listings[0].price > highest_floor.floor_price {
             highest_floor = TraitFloor {
                 trait_id: t.trait_id.clone(),
@@ -66,7 +83,7 @@ pub async fn get_most_valued_trait_floor(
         token_traits_filtered = token_traits.clone();
         let mut highest_floor = TraitFloor::default();
         for t in token_traits_filtered {
-            let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id).await?;
+            let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id, ts).await?;
             if !trait_listings.is_empty() && trait_listings[0].price > highest_floor.floor_price {
                 highest_floor = TraitFloor {
                     trait_id: t.trait_id.clone(),
@@ -90,8 +107,9 @@ pub async fn get_flattening_staircase_price(
     collection_floor: f64,
     token_traits: Vec<TraitRarities>,
     cutoff: f64,
+    ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
-    let floors = get_all_traits_floor(conn, collection_slug, token_traits, cutoff)
+    let floors = get_all_traits_floor(conn, collection_slug, token_traits, cutoff, ts)
         .await?
         .iter()
         .map(|f| f.floor_price)
@@ -119,6 +137,7 @@ pub async fn get_all_traits_floor(
     collection_slug: &str,
     token_traits: Vec<TraitRarities>,
     cutoff: f64,
+    ts: &NaiveDateTime,
 ) -> Result<Vec<TraitFloor>> {
     let mut token_traits_filtered = token_traits
         .iter()
@@ -133,7 +152,7 @@ pub async fn get_all_traits_floor(
 
     let mut floors = vec![];
     for t in token_traits_filtered {
-        let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id).await?;
+        let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id, ts).await?;
         if !trait_listings.is_empty() {
             floors.push(TraitFloor {
                 trait_id: t.trait_id.clone(),
@@ -152,6 +171,7 @@ pub async fn get_rarest_trait_floor(
     conn: &mut PgConnection,
     collection_slug: &str,
     token_traits: Vec<TraitRarities>,
+    ts: &NaiveDateTime,
 ) -> Result<Option<RarestTraitFloor>> {
     let mut token_traits = token_traits.clone();
     token_traits.sort_by(|a, b| a.rarity.partial_cmp(&b.rarity).unwrap());
@@ -160,7 +180,7 @@ pub async fn get_rarest_trait_floor(
         return Ok(None);
     }
 
-    let listings = get_trait_listings(conn, collection_slug, &token_traits[0].trait_id).await?;
+    let listings = get_trait_listings(conn, collection_slug, &token_traits[0].trait_id, ts).await?;
     if !listings.is_empty() {
         Ok(Some(RarestTraitFloor {
             trait_id: token_traits[0].trait_id.clone(),
@@ -177,6 +197,7 @@ pub async fn get_rarity_weighted_floor(
     collection_slug: &str,
     traits: Vec<TraitRarities>,
     cutoff: f64,
+    ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
     let token_traits = traits
         .clone()
@@ -185,14 +206,14 @@ pub async fn get_rarity_weighted_floor(
         .collect::<Vec<_>>();
 
     if token_traits.is_empty() {
-        return Ok(get_rarest_trait_floor(conn, collection_slug, traits)
+        return Ok(get_rarest_trait_floor(conn, collection_slug, traits, ts)
             .await?
             .map(|f| f.floor_price));
     }
 
     let mut floors = vec![];
     for t in token_traits {
-        let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id).await?;
+        let trait_listings = get_trait_listings(conn, collection_slug, &t.trait_id, ts).await?;
         if !trait_listings.is_empty() {
             floors.push((
                 trait_listings[0].token_id,
@@ -219,8 +240,9 @@ pub async fn get_last_sale_price(
     conn: &mut PgConnection,
     collection_slug: &str,
     token_id: i32,
+    ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
-    let asset_sales = get_asset_sales(conn, collection_slug, token_id).await?;
+    let asset_sales = get_asset_sales(conn, collection_slug, token_id, ts).await?;
 
     if !asset_sales.is_empty() {
         Ok(Some(asset_sales.last().unwrap().price))
@@ -235,9 +257,10 @@ pub async fn get_most_valued_trait_last_sale_avg(
     nr: Option<usize>,
     token_traits: Vec<TraitRarities>,
     cutoff: f64,
+    ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
     let most_valuable_trait =
-        get_most_valued_trait_floor(conn, collection_slug, token_traits, cutoff).await?;
+        get_most_valued_trait_floor(conn, collection_slug, token_traits, cutoff, ts).await?;
 
     let trait_sales = if most_valuable_trait.is_some() {
         get_average_trait_sales_nr(
@@ -245,6 +268,7 @@ pub async fn get_most_valued_trait_last_sale_avg(
             collection_slug,
             &most_valuable_trait.unwrap().trait_id,
             nr,
+            ts,
         )
         .await?
     } else {
@@ -258,20 +282,16 @@ pub async fn get_last_sale_relative_to_collection_avg(
     conn: &mut PgConnection,
     collection_slug: &str,
     last_sale: &Option<TokenSale>,
+    window: &Window,
+    ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
     if last_sale.is_some() {
         let last_sale = last_sale.clone().unwrap();
-        let avg_at_sale =
-            match get_average_collection_sales_at_ts(conn, collection_slug, &last_sale.time).await?
-            {
-                Some(v) => v,
-                None => return Ok(None),
-            };
-
-        let avg_now = match get_average_collection_sales_at_ts(
+        let avg_at_sale = match get_average_collection_sales_at_ts(
             conn,
             collection_slug,
-            &Utc::now().naive_utc(),
+            window,
+            &last_sale.time,
         )
         .await?
         {
@@ -279,6 +299,12 @@ pub async fn get_last_sale_relative_to_collection_avg(
             None => return Ok(None),
         };
 
+        let avg_now =
+            match get_average_collection_sales_at_ts(conn, collection_slug, window, ts).await? {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+
         Ok(Some((last_sale.price / avg_at_sale) * avg_now))
     } else {
         Ok(None)
@@ -290,27 +316,30 @@ pub async fn get_last_sale_relative_to_trait_avg(
     collection_slug: &str,
     trait_name: &str,
     last_sale: &Option<TokenSale>,
+    window: &Window,
+    ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
     if last_sale.is_some() {
         let last_sale = last_sale.clone().unwrap();
-        let avg_at_sale =
-            match get_average_trait_sales_at_ts(conn, collection_slug, trait_name, &last_sale.time)
-                .await?
-            {
-                Some(v) => v,
-                None => return Ok(None),
-            };
-        let avg_now = match get_average_trait_sales_at_ts(
+        let avg_at_sale = match get_average_trait_sales_at_ts(
             conn,
             collection_slug,
             trait_name,
-            &Utc::now().naive_utc(),
+            window,
+            &last_sale.time,
         )
         .await?
         {
             Some(v) => v,
             None => return Ok(None),
         };
+        let avg_now =
+            match get_average_trait_sales_at_ts(conn, collection_slug, trait_name, window, ts)
+                .await?
+            {
+                Some(v) => v,
+                None => return Ok(None),
+            };
 
         Ok(Some((last_sale.price / avg_at_sale) * avg_now))
     } else {
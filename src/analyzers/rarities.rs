@@ -3,6 +3,7 @@ use crate::storage::read::*;
 use crate::storage::Trait;
 use anyhow::Result;
 use sqlx::PgConnection;
+use std::collections::HashMap;
 
 pub async fn get_trait_rarities(
     conn: &mut PgConnection,
@@ -11,16 +12,34 @@ pub async fn get_trait_rarities(
 ) -> Result<Vec<TraitRarities>> {
     let collection = read_collection(conn, collection_slug).await?;
     let traits = read_traits_for_asset(conn, collection_slug, token_id).await?;
+
+    Ok(to_trait_rarities(traits, collection.total_supply))
+}
+
+/// Rarities of a hypothetical token, trait ids that are not stored are left out
+pub async fn get_trait_set_rarities(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    trait_ids: &[String],
+) -> Result<Vec<TraitRarities>> {
+    let collection = read_collection(conn, collection_slug).await?;
+    let traits = read_traits_by_ids(conn, collection_slug, trait_ids).await?;
+
+    Ok(to_trait_rarities(traits, collection.total_supply))
+}
+
+/// Sorted from rarest to most common
+fn to_trait_rarities(traits: HashMap<String, i32>, total_supply: i32) -> Vec<TraitRarities> {
     let mut traits_vec: Vec<(String, i32)> = traits.into_iter().collect();
     traits_vec.sort_by(|a, b| a.1.cmp(&b.1));
 
-    Ok(traits_vec
+    traits_vec
         .into_iter()
         .map(|(t, c)| TraitRarities {
             trait_id: t,
-            rarity: c as f64 / collection.total_supply as f64,
+            rarity: c as f64 / total_supply as f64,
         })
-        .collect())
+        .collect()
 }
 
 pub fn get_collection_avg_trait_rarity(traits: &[Trait]) -> Result<f64> {
@@ -0,0 +1,231 @@
+use crate::storage::read::{
+    read_asset_traits_for_collection, read_token_ids_for_collection, read_traits_for_collection,
+};
+use crate::storage::write::write_rarity_scores;
+use crate::storage::{Collection, RarityScore};
+use anyhow::Result;
+use sqlx::PgConnection;
+use std::collections::{BTreeSet, HashMap};
+
+/// Trait frequencies of a collection. Every trait type a token lacks counts as a `missing`
+/// pseudo-trait and the number of traits a token has counts as one more trait
+#[derive(Debug, Clone)]
+pub struct RarityModel {
+    nr_tokens: usize,
+    trait_types: HashMap<String, String>,
+    trait_counts: HashMap<String, usize>,
+    missing_counts: HashMap<String, usize>,
+    nr_traits_counts: HashMap<usize, usize>,
+}
+
+/// Scores of one token, (statistical rarity, rarity score, information content)
+pub type Scores = (f64, f64, f64);
+
+impl RarityModel {
+    /// `trait_types` maps trait ids to their type, trait types to ignore are left out of it
+    pub fn new(
+        token_ids: &[i32],
+        token_traits: &HashMap<i32, Vec<String>>,
+        trait_types: HashMap<String, String>,
+    ) -> Self {
+        let types = trait_types.values().cloned().collect::<BTreeSet<_>>();
+
+        let mut trait_counts = HashMap::<String, usize>::new();
+        let mut missing_counts = types
+            .iter()
+            .map(|t| (t.clone(), 0))
+            .collect::<HashMap<_, _>>();
+        let mut nr_traits_counts = HashMap::<usize, usize>::new();
+
+        for token_id in token_ids {
+            let traits = known_traits(
+                &trait_types,
+                token_traits.get(token_id).map(|t| t.as_slice()),
+            );
+            for t in &traits {
+                *trait_counts.entry(t.to_string()).or_default() += 1;
+            }
+            for trait_type in &types {
+                if !traits.iter().any(|t| &trait_types[*t] == trait_type) {
+                    *missing_counts.get_mut(trait_type).unwrap() += 1;
+                }
+            }
+            *nr_traits_counts.entry(traits.len()).or_default() += 1;
+        }
+
+        Self {
+            nr_tokens: token_ids.len(),
+            trait_types,
+            trait_counts,
+            missing_counts,
+            nr_traits_counts,
+        }
+    }
+
+    /// Works for trait sets no token has, unknown trait ids are skipped
+    pub fn score(&self, trait_ids: &[String]) -> Scores {
+        let frequencies = self.frequencies(trait_ids);
+
+        (
+            frequencies.iter().product(),
+            frequencies.iter().map(|f| 1f64 / f).sum(),
+            frequencies.iter().map(|f| -f.log2()).sum(),
+        )
+    }
+
+    fn frequencies(&self, trait_ids: &[String]) -> Vec<f64> {
+        let traits = known_traits(&self.trait_types, Some(trait_ids));
+        // combinations no token has count as if the token were the only one
+        let frequency = |count: Option<&usize>| {
+            count.copied().unwrap_or_default().max(1) as f64 / self.nr_tokens.max(1) as f64
+        };
+
+        let mut frequencies = traits
+            .iter()
+            .map(|t| frequency(self.trait_counts.get(*t)))
+            .collect::<Vec<_>>();
+        for (trait_type, count) in &self.missing_counts {
+            if !traits.iter().any(|t| &self.trait_types[*t] == trait_type) {
+                frequencies.push(frequency(Some(count)));
+            }
+        }
+        frequencies.push(frequency(self.nr_traits_counts.get(&traits.len())));
+
+        frequencies
+    }
+}
+
+fn known_traits<'a>(
+    trait_types: &HashMap<String, String>,
+    traits: Option<&'a [String]>,
+) -> Vec<&'a str> {
+    traits
+        .into_iter()
+        .flatten()
+        .filter(|t| trait_types.contains_key(*t))
+        .map(|t| t.as_str())
+        .collect()
+}
+
+pub async fn get_rarity_model(
+    conn: &mut PgConnection,
+    collection: &Collection,
+) -> Result<RarityModel> {
+    let token_ids = read_token_ids_for_collection(conn, &collection.slug).await?;
+    let token_traits = read_asset_traits_for_collection(conn, &collection.slug).await?;
+    let trait_types = read_traits_for_collection(conn, &collection.slug)
+        .await?
+        .into_iter()
+        .filter(|t| {
+            !collection
+                .ignored_trait_types_rarity
+                .contains(&t.trait_type)
+        })
+        .map(|t| (t.trait_id, t.trait_type))
+        .collect();
+
+    Ok(RarityModel::new(&token_ids, &token_traits, trait_types))
+}
+
+/// Scores and ranks every asset of the collection, returns the number of stored scores
+pub async fn store_rarity_scores(
+    conn: &mut PgConnection,
+    collection: &Collection,
+) -> Result<usize> {
+    let model = get_rarity_model(conn, collection).await?;
+    let token_ids = read_token_ids_for_collection(conn, &collection.slug).await?;
+    let token_traits = read_asset_traits_for_collection(conn, &collection.slug).await?;
+
+    let scores = get_rarity_scores(&collection.slug, &model, &token_ids, &token_traits);
+    write_rarity_scores(conn, &collection.slug, &scores).await?;
+
+    Ok(scores.len())
+}
+
+fn get_rarity_scores(
+    collection_slug: &str,
+    model: &RarityModel,
+    token_ids: &[i32],
+    token_traits: &HashMap<i32, Vec<String>>,
+) -> Vec<RarityScore> {
+    let scores = token_ids
+        .iter()
+        .map(|id| {
+            model.score(
+                token_traits
+                    .get(id)
+                    .map(|t| t.as_slice())
+                    .unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // a lower statistical rarity is rarer, for the other scores a higher one
+    let statistical_ranks = get_ranks(&scores.iter().map(|s| s.0).collect::<Vec<_>>());
+    let score_ranks = get_ranks(&scores.iter().map(|s| -s.1).collect::<Vec<_>>());
+    let information_ranks = get_ranks(&scores.iter().map(|s| -s.2).collect::<Vec<_>>());
+
+    token_ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| RarityScore {
+            collection_slug: collection_slug.to_string(),
+            token_id: *id,
+            statistical_rarity: scores[i].0,
+            rarity_score: scores[i].1,
+            information_content: scores[i].2,
+            statistical_rarity_rank: statistical_ranks[i],
+            rarity_score_rank: score_ranks[i],
+            information_content_rank: information_ranks[i],
+        })
+        .collect()
+}
+
+/// Rank 1 for the lowest value, equal values share a rank
+fn get_ranks(values: &[f64]) -> Vec<i32> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap());
+
+    let mut ranks = vec![0; values.len()];
+    for (position, i) in order.iter().enumerate() {
+        ranks[*i] = match position {
+            0 => 1,
+            _ if values[*i] == values[order[position - 1]] => ranks[order[position - 1]],
+            _ => position as i32 + 1,
+        };
+    }
+
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rarity_model() {
+        let trait_types = HashMap::from([
+            ("hat:red".to_string(), "hat".to_string()),
+            ("eyes:laser".to_string(), "eyes".to_string()),
+            ("eyes:none".to_string(), "eyes".to_string()),
+        ]);
+        let token_traits = HashMap::from([
+            (1, vec!["hat:red".to_string(), "eyes:laser".to_string()]),
+            (2, vec!["eyes:none".to_string()]),
+            (3, vec!["eyes:none".to_string()]),
+            (4, vec!["eyes:none".to_string()]),
+        ]);
+        let model = RarityModel::new(&[1, 2, 3, 4], &token_traits, trait_types);
+
+        // hat:red, eyes:laser and two traits all occur once
+        let (statistical, score, information) = model.score(&token_traits[&1]);
+        assert_eq!(statistical, 0.25f64.powi(3));
+        assert_eq!(score, 12.0);
+        assert_eq!(information, 6.0);
+
+        let scores = get_rarity_scores("c", &model, &[1, 2, 3, 4], &token_traits);
+        assert_eq!(scores[0].rarity_score_rank, 1);
+        assert_eq!(scores[1].rarity_score_rank, 2);
+        assert_eq!(scores[3].statistical_rarity_rank, 2);
+    }
+}
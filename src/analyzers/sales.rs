@@ -1,3 +1,4 @@
+use super::window::Window;
 use super::*;
 use crate::from_wei;
 use crate::storage::read::*;
@@ -5,10 +6,14 @@ use anyhow::Result;
 use chrono::NaiveDateTime;
 use sqlx::PgConnection;
 
+/// Days of the collection and trait averages of collections without an analysis window
+pub static AVG_PRICE_WINDOW_DAYS: i64 = 60;
+
 pub async fn get_trait_sales(
     conn: &mut PgConnection,
     collection_slug: &str,
     trait_name: &str,
+    ts: &NaiveDateTime,
 ) -> Result<Vec<TokenSale>> {
     let mut all_sales = read_sales_for_trait(conn, collection_slug, trait_name)
         .await
@@ -18,6 +23,7 @@ pub async fn get_trait_sales(
 
     Ok(all_sales
         .into_iter()
+        .filter(|t| (t.timestamp as i64) < ts.timestamp())
         .map(|t| TokenSale {
             token_id: t.token_id as i32,
             time: NaiveDateTime::from_timestamp(t.timestamp as i64, 0),
@@ -30,6 +36,7 @@ pub async fn get_asset_sales(
     conn: &mut PgConnection,
     collection_slug: &str,
     token_id: i32,
+    ts: &NaiveDateTime,
 ) -> Result<Vec<TokenSale>> {
     let mut all_sales = read_sales_for_asset(conn, collection_slug, token_id)
         .await
@@ -39,6 +46,7 @@ pub async fn get_asset_sales(
 
     Ok(all_sales
         .into_iter()
+        .filter(|t| (t.timestamp as i64) < ts.timestamp())
         .map(|t| TokenSale {
             token_id: t.token_id as i32,
             time: NaiveDateTime::from_timestamp(t.timestamp as i64, 0),
@@ -52,8 +60,9 @@ pub async fn get_average_trait_sales_nr(
     collection_slug: &str,
     trait_name: &str,
     nr: Option<usize>,
+    ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
-    let sale_history = get_trait_sales(conn, collection_slug, trait_name).await?;
+    let sale_history = get_trait_sales(conn, collection_slug, trait_name, ts).await?;
     let count = nr.unwrap_or(sale_history.len());
 
     if sale_history.len() < count || sale_history.is_empty() {
@@ -73,16 +82,19 @@ pub async fn get_average_trait_sales_nr(
 pub async fn get_average_collection_sales_at_ts(
     conn: &mut PgConnection,
     collection_slug: &str,
+    window: &Window,
     ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
-    read_30d_avg_price_collection_at_ts(conn, collection_slug, ts).await
+    read_avg_price_collection_at_ts(conn, collection_slug, ts, window.days).await
 }
 
+/// Read from the trait index, in ETH
 pub async fn get_average_trait_sales_at_ts(
     conn: &mut PgConnection,
     collection_slug: &str,
     trait_name: &str,
+    window: &Window,
     ts: &NaiveDateTime,
 ) -> Result<Option<f64>> {
-    read_30d_avg_price_trait_at_ts(conn, collection_slug, trait_name, ts).await
+    read_avg_price_trait_index_at_ts(conn, collection_slug, trait_name, ts, window.days).await
 }
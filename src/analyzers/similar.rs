@@ -0,0 +1,175 @@
+use crate::analyzers::fx::{Currency, EthRates};
+use crate::from_wei;
+use crate::storage::read::{
+    read_all_asset_traits_for_collection, read_collection, read_latest_sale_for_tokens,
+    read_listed_for_collection_at_ts,
+};
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::PgConnection;
+use std::collections::{HashMap, HashSet};
+
+/// A token close to the requested one, with its current listing and last sale
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct SimilarToken {
+    pub token_id: i32,
+    /// Weighted Jaccard similarity of the traits, 1 for identical traits
+    pub similarity: f64,
+    pub shared_traits: Vec<String>,
+    pub listing_price: Option<f64>,
+    pub listed_at: Option<NaiveDateTime>,
+    pub last_sale_price: Option<f64>,
+    pub last_sale_time: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct SimilarTokens {
+    pub collection_slug: String,
+    pub token_id: i32,
+    /// Most similar first
+    pub similar: Vec<SimilarToken>,
+    pub currency: Currency,
+}
+
+impl SimilarTokens {
+    /// Converts the listings at the latest rate and every last sale at the rate of its time
+    pub fn in_currency(mut self, rates: &EthRates) -> Self {
+        for s in self.similar.iter_mut() {
+            s.listing_price = s.listing_price.map(|p| p * rates.rate(None));
+            s.last_sale_price = s
+                .last_sale_price
+                .map(|p| p * rates.rate(s.last_sale_time.as_ref()));
+        }
+        self.currency = rates.currency;
+        self
+    }
+}
+
+/// The `limit` tokens sharing the most of the token's traits, None if the token is unknown
+pub async fn get_similar_tokens(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_id: i32,
+    limit: usize,
+) -> Result<Option<SimilarTokens>> {
+    let collection = read_collection(conn, collection_slug).await?;
+    let token_traits = read_all_asset_traits_for_collection(conn, collection_slug)
+        .await?
+        .into_iter()
+        .map(|(id, traits)| {
+            let traits = traits
+                .into_iter()
+                .filter(|t| {
+                    !collection
+                        .ignored_trait_types_overlap
+                        .iter()
+                        .any(|i| t.starts_with(&format!("{}:", i)))
+                })
+                .collect::<HashSet<_>>();
+            (id, traits)
+        })
+        .collect::<HashMap<_, _>>();
+
+    if !token_traits.contains_key(&token_id) {
+        return Ok(None);
+    }
+    let ranked = rank_similar(&token_traits, token_id, limit);
+
+    let token_ids = ranked.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let listings = read_listed_for_collection_at_ts(conn, collection_slug, &Utc::now().naive_utc())
+        .await?
+        .into_iter()
+        .map(|l| (l.token_id, l))
+        .collect::<HashMap<_, _>>();
+    let sales = read_latest_sale_for_tokens(conn, collection_slug, &token_ids)
+        .await?
+        .into_iter()
+        .map(|s| (s.token_id, s))
+        .collect::<HashMap<_, _>>();
+
+    let traits = &token_traits[&token_id];
+    let similar = ranked
+        .into_iter()
+        .map(|(id, similarity)| SimilarToken {
+            token_id: id,
+            similarity,
+            shared_traits: token_traits[&id].intersection(traits).cloned().collect(),
+            listing_price: listings.get(&id).and_then(|l| l.price).map(from_wei),
+            listed_at: listings
+                .get(&id)
+                .map(|l| NaiveDateTime::from_timestamp(l.timestamp as i64, 0)),
+            last_sale_price: sales.get(&id).map(|s| from_wei(s.price)),
+            last_sale_time: sales
+                .get(&id)
+                .map(|s| NaiveDateTime::from_timestamp(s.timestamp as i64, 0)),
+        })
+        .collect();
+
+    Ok(Some(SimilarTokens {
+        collection_slug: collection_slug.to_string(),
+        token_id,
+        similar,
+        currency: Currency::Eth,
+    }))
+}
+
+/// Other tokens by weighted Jaccard similarity to `token_id`, every trait weighs the inverse
+/// of its frequency so sharing a rare trait counts more than sharing a common one
+fn rank_similar(
+    token_traits: &HashMap<i32, HashSet<String>>,
+    token_id: i32,
+    limit: usize,
+) -> Vec<(i32, f64)> {
+    let mut counts = HashMap::<&str, usize>::new();
+    for t in token_traits.values().flatten() {
+        *counts.entry(t).or_default() += 1;
+    }
+    let weight = |t: &String| token_traits.len() as f64 / counts[t.as_str()] as f64;
+
+    let traits = &token_traits[&token_id];
+    let mut ranked = token_traits
+        .iter()
+        .filter(|(id, _)| **id != token_id)
+        .map(|(id, other)| {
+            let shared = traits.intersection(other).map(weight).sum::<f64>();
+            let all = traits.union(other).map(weight).sum::<f64>();
+            (*id, if all > 0f64 { shared / all } else { 0f64 })
+        })
+        .filter(|(_, similarity)| *similarity > 0f64)
+        .collect::<Vec<_>>();
+
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_similar() {
+        let traits = |ts: &[&str]| ts.iter().map(|t| t.to_string()).collect::<HashSet<_>>();
+        let token_traits = HashMap::from([
+            (1, traits(&["hat:red", "eyes:laser"])),
+            (2, traits(&["hat:red", "eyes:none"])),
+            (3, traits(&["hat:blue", "eyes:laser"])),
+            (4, traits(&["hat:blue", "eyes:none"])),
+            (5, traits(&["hat:blue", "eyes:none"])),
+            (6, traits(&["hat:red", "eyes:none"])),
+        ]);
+
+        let ranked = rank_similar(&token_traits, 1, 10);
+
+        // eyes:laser is rarer than hat:red, token 4 and 5 share nothing
+        assert_eq!(
+            ranked.iter().map(|r| r.0).collect::<Vec<_>>(),
+            vec![3, 2, 6]
+        );
+        assert!(ranked[0].1 > ranked[1].1);
+
+        let (id, similarity) = rank_similar(&token_traits, 4, 1)[0];
+        assert_eq!(id, 5);
+        assert!((similarity - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,396 @@
+use super::comps::{get_comps, get_comps_price};
+use super::hedonic::get_hedonic_price;
+use super::prices::*;
+use super::rarities::get_trait_rarities;
+use super::sales::*;
+use super::window::Window;
+use super::*;
+use crate::storage::Collection;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use futures::future::BoxFuture;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// Everything a strategy may need to price a single token, computed once per profile
+#[derive(Clone, Debug)]
+pub struct PricingContext {
+    pub collection_slug: String,
+    /// None when appraising a hypothetical set of traits
+    pub token_id: Option<i32>,
+    pub token_traits: Vec<TraitRarities>,
+    pub rarest_trait: String,
+    pub most_valuable_trait: Option<TraitFloor>,
+    pub cutoff: f64,
+    pub collection_floor: f64,
+    pub last_sale: Option<TokenSale>,
+    /// Lookback of the collection and trait price averages
+    pub window: Window,
+    /// Moment the token is priced at, strategies must not use data from after it
+    pub ts: NaiveDateTime,
+}
+
+impl PricingContext {
+    /// Builds the context purely from data known at `ts`, including the collection floor. The
+    /// traits are read from the token unless given, without traits only the collection's floor
+    /// is known. `window` overrides the collection's analysis window
+    pub async fn make(
+        conn: &mut PgConnection,
+        collection: &Collection,
+        token_id: Option<i32>,
+        token_traits: Option<Vec<TraitRarities>>,
+        window: Option<Window>,
+        ts: &NaiveDateTime,
+    ) -> Result<Self> {
+        let token_traits = match (token_traits, token_id) {
+            (Some(t), _) => t,
+            (None, Some(id)) => get_trait_rarities(conn, &collection.slug, id).await?,
+            (None, None) => vec![],
+        };
+
+        let most_valuable_trait = get_most_valued_trait_floor(
+            conn,
+            &collection.slug,
+            token_traits.clone(),
+            collection.rarity_cutoff,
+            ts,
+        )
+        .await?;
+
+        // rebuilt from the listings for current and past prices alike, so both agree
+        let collection_floor = get_collection_floor_at_ts(conn, &collection.slug, ts)
+            .await?
+            .unwrap_or_default();
+
+        let last_sale = match token_id {
+            Some(id) => get_asset_sales(conn, &collection.slug, id, ts)
+                .await?
+                .last()
+                .cloned(),
+            None => None,
+        };
+
+        Ok(Self {
+            collection_slug: collection.slug.clone(),
+            token_id,
+            rarest_trait: token_traits
+                .first()
+                .map(|t| t.trait_id.clone())
+                .unwrap_or_default(),
+            token_traits,
+            most_valuable_trait,
+            cutoff: collection.rarity_cutoff,
+            collection_floor,
+            last_sale,
+            window: Window::resolve(window, collection, AVG_PRICE_WINDOW_DAYS),
+            ts: *ts,
+        })
+    }
+}
+
+pub trait PricingStrategy: Send + Sync {
+    /// Unique name, used to enable the strategy on a collection and to report its price
+    fn name(&self) -> &'static str;
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>>;
+}
+
+/// All known strategies, new strategies need to be registered here
+pub fn registry() -> Vec<Box<dyn PricingStrategy>> {
+    vec![
+        Box::new(CollectionFloor),
+        Box::new(LastSale),
+        Box::new(MostRareTraitFloor),
+        Box::new(MostValuedTraitFloor),
+        Box::new(RarityWeightedFloor),
+        Box::new(FloorStaircase),
+        Box::new(AvgLastThreeMvtSales),
+        Box::new(LastSaleRelativeCollectionAvg),
+        Box::new(LastSaleRelativeMvtAvg),
+        Box::new(Hedonic),
+        Box::new(CompsWeighted),
+    ]
+}
+
+/// Strategies enabled for a collection, an empty list enables all of them
+pub fn enabled_strategies(enabled: &[String]) -> Vec<Box<dyn PricingStrategy>> {
+    registry()
+        .into_iter()
+        .filter(|s| enabled.is_empty() || enabled.iter().any(|e| e == s.name()))
+        .collect()
+}
+
+pub fn is_registered(name: &str) -> bool {
+    registry().iter().any(|s| s.name() == name)
+}
+
+pub async fn run_strategies(
+    conn: &mut PgConnection,
+    strategies: &[Box<dyn PricingStrategy>],
+    ctx: &PricingContext,
+) -> Result<HashMap<String, Option<f64>>> {
+    let mut prices = HashMap::<String, Option<f64>>::new();
+    for strategy in strategies {
+        log::info!("Getting {}", strategy.name());
+        let price = strategy.price(conn, ctx).await?;
+        prices.insert(strategy.name().to_string(), price);
+    }
+    Ok(prices)
+}
+
+/// Returns (min, max, avg) over all non-zero strategy prices, min never goes below the floor
+pub fn get_price_range(
+    prices: &HashMap<String, Option<f64>>,
+    collection_floor: f64,
+) -> (f64, f64, f64) {
+    let mut prices = prices
+        .values()
+        .map(|p| p.unwrap_or(0f64))
+        .filter(|p| p > &0f64)
+        .collect::<Vec<_>>();
+
+    prices.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let max_price = prices.first().copied().unwrap_or(collection_floor);
+
+    prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let min_price = f64::max(
+        prices.first().copied().unwrap_or(collection_floor),
+        collection_floor,
+    );
+
+    (min_price, max_price, (max_price + min_price) / 2f64)
+}
+
+pub struct CollectionFloor;
+impl PricingStrategy for CollectionFloor {
+    fn name(&self) -> &'static str {
+        "collection_floor"
+    }
+
+    fn price<'a>(
+        &'a self,
+        _conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move { Ok(Some(ctx.collection_floor)) })
+    }
+}
+
+pub struct LastSale;
+impl PricingStrategy for LastSale {
+    fn name(&self) -> &'static str {
+        "last_sale"
+    }
+
+    fn price<'a>(
+        &'a self,
+        _conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move { Ok(ctx.last_sale.as_ref().map(|s| s.price)) })
+    }
+}
+
+pub struct MostRareTraitFloor;
+impl PricingStrategy for MostRareTraitFloor {
+    fn name(&self) -> &'static str {
+        "most_rare_trait_floor"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move {
+            Ok(
+                get_trait_floor(conn, &ctx.collection_slug, &ctx.rarest_trait, &ctx.ts)
+                    .await?
+                    .map(|t| t.floor_price),
+            )
+        })
+    }
+}
+
+pub struct MostValuedTraitFloor;
+impl PricingStrategy for MostValuedTraitFloor {
+    fn name(&self) -> &'static str {
+        "most_valued_trait_floor"
+    }
+
+    fn price<'a>(
+        &'a self,
+        _conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move { Ok(ctx.most_valuable_trait.as_ref().map(|t| t.floor_price)) })
+    }
+}
+
+pub struct RarityWeightedFloor;
+impl PricingStrategy for RarityWeightedFloor {
+    fn name(&self) -> &'static str {
+        "rarity_weighted_floor"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move {
+            get_rarity_weighted_floor(
+                conn,
+                &ctx.collection_slug,
+                ctx.token_traits.clone(),
+                ctx.cutoff,
+                &ctx.ts,
+            )
+            .await
+        })
+    }
+}
+
+pub struct FloorStaircase;
+impl PricingStrategy for FloorStaircase {
+    fn name(&self) -> &'static str {
+        "floor_staircase_price"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move {
+            get_flattening_staircase_price(
+                conn,
+                &ctx.collection_slug,
+                ctx.collection_floor,
+                ctx.token_traits.clone(),
+                ctx.cutoff,
+                &ctx.ts,
+            )
+            .await
+        })
+    }
+}
+
+pub struct AvgLastThreeMvtSales;
+impl PricingStrategy for AvgLastThreeMvtSales {
+    fn name(&self) -> &'static str {
+        "avg_last_three_mvt_sales"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move {
+            match &ctx.most_valuable_trait {
+                Some(t) => {
+                    get_average_trait_sales_nr(
+                        conn,
+                        &ctx.collection_slug,
+                        &t.trait_id,
+                        Some(3),
+                        &ctx.ts,
+                    )
+                    .await
+                }
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+pub struct LastSaleRelativeCollectionAvg;
+impl PricingStrategy for LastSaleRelativeCollectionAvg {
+    fn name(&self) -> &'static str {
+        "last_sale_relative_collection_avg"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move {
+            get_last_sale_relative_to_collection_avg(
+                conn,
+                &ctx.collection_slug,
+                &ctx.last_sale,
+                &ctx.window,
+                &ctx.ts,
+            )
+            .await
+        })
+    }
+}
+
+pub struct LastSaleRelativeMvtAvg;
+impl PricingStrategy for LastSaleRelativeMvtAvg {
+    fn name(&self) -> &'static str {
+        "last_sale_relative_mvt_avg"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move {
+            match &ctx.most_valuable_trait {
+                Some(t) => {
+                    get_last_sale_relative_to_trait_avg(
+                        conn,
+                        &ctx.collection_slug,
+                        &t.trait_id,
+                        &ctx.last_sale,
+                        &ctx.window,
+                        &ctx.ts,
+                    )
+                    .await
+                }
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+pub struct Hedonic;
+impl PricingStrategy for Hedonic {
+    fn name(&self) -> &'static str {
+        "hedonic_model"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move {
+            get_hedonic_price(conn, &ctx.collection_slug, &ctx.token_traits, &ctx.ts).await
+        })
+    }
+}
+
+pub struct CompsWeighted;
+impl PricingStrategy for CompsWeighted {
+    fn name(&self) -> &'static str {
+        "comps_weighted"
+    }
+
+    fn price<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        ctx: &'a PricingContext,
+    ) -> BoxFuture<'a, Result<Option<f64>>> {
+        Box::pin(async move { Ok(get_comps_price(&get_comps(conn, ctx).await?)) })
+    }
+}
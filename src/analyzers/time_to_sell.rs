@@ -0,0 +1,342 @@
+use crate::from_wei;
+use crate::storage::read::{
+    read_all_listings_for_collection, read_assets_with_traits, read_sales_for_collection_after_ts,
+    read_snapshot_avg_prices_at_ts,
+};
+use crate::storage::Listing;
+use anyhow::Result;
+use cached::proc_macro::cached;
+use chrono::{Duration, NaiveDateTime};
+use sqlx::PgConnection;
+use std::collections::{HashMap, HashSet};
+
+/// Only listings this recent are used for the estimates
+static LISTINGS_MAX_AGE_DAYS: i64 = 180;
+/// Listings priced within this factor of the asked price relative to fair value are comparable
+static PRICE_RATIO_TOLERANCE: f64 = 1.15;
+/// Window of the collection average used as fair value of tokens without a price snapshot
+static FALLBACK_AVG_DAYS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EpisodeOutcome {
+    Sold,
+    Cancelled,
+    /// Still listed
+    Open,
+}
+
+/// A token from being listed until it sold or the listing was cancelled
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingEpisode {
+    pub token_id: i32,
+    pub listed_at: NaiveDateTime,
+    /// Every price the token was listed at in ETH, price cuts included, oldest first
+    pub prices: Vec<(NaiveDateTime, f64)>,
+    pub ended_at: Option<NaiveDateTime>,
+    pub outcome: EpisodeOutcome,
+}
+
+/// Part of an episode listed at a single price
+#[derive(Debug, Clone)]
+pub struct ListingSegment {
+    pub token_id: i32,
+    pub price_to_fair_value: f64,
+    pub days: f64,
+    /// The token sold at the end of the segment
+    pub sold: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Default, PartialEq)]
+pub struct DaysToSale {
+    /// Days listed per sale among comparable listings, None if none of them sold
+    pub expected_days: Option<f64>,
+    pub nr_listings: usize,
+    pub nr_sold: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct TimeToSell {
+    pub price: f64,
+    pub price_to_fair_value: f64,
+    pub collection: DaysToSale,
+    /// Estimates from listings of tokens having the trait only
+    pub traits: HashMap<String, DaysToSale>,
+}
+
+/// Expected days to sell the token at each of `prices`, `fair_value` is the token's current
+/// valuation and listings are compared by their price relative to the valuation at the time
+pub async fn get_time_to_sell(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    trait_ids: &[String],
+    prices: &[f64],
+    fair_value: f64,
+    ts: &NaiveDateTime,
+) -> Result<Vec<TimeToSell>> {
+    if fair_value <= 0f64 {
+        return Ok(vec![]);
+    }
+    let segments = get_listing_segments(conn, collection_slug, ts).await?;
+
+    let mut trait_tokens = HashMap::new();
+    for t in trait_ids {
+        let token_ids = read_assets_with_traits(conn, collection_slug, vec![t.clone()])
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        trait_tokens.insert(t.clone(), token_ids);
+    }
+
+    Ok(prices
+        .iter()
+        .map(|price| {
+            let ratio = price / fair_value;
+            TimeToSell {
+                price: *price,
+                price_to_fair_value: ratio,
+                collection: estimate_days_to_sale(segments.iter(), ratio),
+                traits: trait_tokens
+                    .iter()
+                    .map(|(t, tokens)| {
+                        let segments = segments.iter().filter(|s| tokens.contains(&s.token_id));
+                        (t.clone(), estimate_days_to_sale(segments, ratio))
+                    })
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+#[cached(
+    size = 100,
+    time = 3600,
+    result = true,
+    key = "String",
+    convert = r#"{ format!("{}:{}", collection_slug, ts.timestamp() / 3600) }"#
+)]
+async fn get_listing_segments(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    ts: &NaiveDateTime,
+) -> Result<Vec<ListingSegment>> {
+    let start = *ts - Duration::days(LISTINGS_MAX_AGE_DAYS);
+    let listings = read_all_listings_for_collection(conn, collection_slug)
+        .await?
+        .into_iter()
+        .filter(|l| (l.timestamp as i64) < ts.timestamp())
+        .collect::<Vec<_>>();
+    let episodes = get_listing_episodes(&listings)
+        .into_iter()
+        .filter(|e| e.ended_at.map(|t| t > start).unwrap_or(true))
+        .collect::<Vec<_>>();
+
+    // (token id, start, end, price, sold)
+    let mut parts = vec![];
+    for e in &episodes {
+        let end = e.ended_at.unwrap_or(*ts);
+        for (i, (listed_at, price)) in e.prices.iter().enumerate() {
+            let last = i + 1 == e.prices.len();
+            let until = if last { end } else { e.prices[i + 1].0 };
+            parts.push((
+                e.token_id,
+                *listed_at.max(&start),
+                until,
+                *price,
+                last && e.outcome == EpisodeOutcome::Sold,
+            ));
+        }
+    }
+
+    let fair_values = read_snapshot_avg_prices_at_ts(
+        conn,
+        collection_slug,
+        &parts.iter().map(|p| p.0).collect::<Vec<_>>(),
+        &parts
+            .iter()
+            .map(|p| p.1.timestamp() as i32)
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    let collection_avg = CollectionAvg::new(
+        read_sales_for_collection_after_ts(
+            conn,
+            collection_slug,
+            &(start - Duration::days(FALLBACK_AVG_DAYS)),
+        )
+        .await?
+        .into_iter()
+        .map(|s| (s.timestamp as i64, from_wei(s.price)))
+        .collect(),
+    );
+
+    Ok(parts
+        .into_iter()
+        .zip(fair_values)
+        .filter(|((_, start, end, _, _), _)| end > start)
+        .filter_map(|((token_id, start, end, price, sold), fair_value)| {
+            let fair_value = fair_value.or_else(|| collection_avg.at(&start))?;
+            if fair_value <= 0f64 {
+                return None;
+            }
+            Some(ListingSegment {
+                token_id,
+                price_to_fair_value: price / fair_value,
+                days: (end - start).num_seconds() as f64 / 86400f64,
+                sold,
+            })
+        })
+        .collect())
+}
+
+/// Pairs the listing updates into episodes, `listings` ordered oldest first
+pub fn get_listing_episodes(listings: &[Listing]) -> Vec<ListingEpisode> {
+    let mut open = HashMap::<i32, ListingEpisode>::new();
+    let mut episodes = vec![];
+
+    for l in listings {
+        let time = NaiveDateTime::from_timestamp(l.timestamp as i64, 0);
+        let outcome = match (l.update_type.as_str(), l.price) {
+            ("successful", _) => EpisodeOutcome::Sold,
+            (_, Some(price)) => {
+                open.entry(l.token_id)
+                    .or_insert_with(|| ListingEpisode {
+                        token_id: l.token_id,
+                        listed_at: time,
+                        prices: vec![],
+                        ended_at: None,
+                        outcome: EpisodeOutcome::Open,
+                    })
+                    .prices
+                    .push((time, from_wei(price)));
+                continue;
+            }
+            // cancelled, or not listed when the collection was added
+            (_, None) => EpisodeOutcome::Cancelled,
+        };
+
+        if let Some(mut e) = open.remove(&l.token_id) {
+            e.ended_at = Some(time);
+            e.outcome = outcome;
+            episodes.push(e);
+        }
+    }
+
+    episodes.extend(open.into_values());
+    episodes.sort_by_key(|e| (e.listed_at, e.token_id));
+    episodes
+}
+
+/// Sales are treated as arrivals at a constant rate per listed day, so listings that were
+/// cancelled or are still open count towards the days listed without counting as a sale
+fn estimate_days_to_sale<'a>(
+    segments: impl Iterator<Item = &'a ListingSegment>,
+    price_to_fair_value: f64,
+) -> DaysToSale {
+    let mut days = 0f64;
+    let mut estimate = DaysToSale::default();
+    for s in segments.filter(|s| {
+        s.price_to_fair_value >= price_to_fair_value / PRICE_RATIO_TOLERANCE
+            && s.price_to_fair_value <= price_to_fair_value * PRICE_RATIO_TOLERANCE
+    }) {
+        days += s.days;
+        estimate.nr_listings += 1;
+        if s.sold {
+            estimate.nr_sold += 1;
+        }
+    }
+
+    if estimate.nr_sold > 0 {
+        estimate.expected_days = Some(days / estimate.nr_sold as f64);
+    }
+    estimate
+}
+
+/// Average sale price of the collection over a trailing window, in ETH
+struct CollectionAvg {
+    timestamps: Vec<i64>,
+    cumulative: Vec<f64>,
+}
+
+impl CollectionAvg {
+    fn new(mut sales: Vec<(i64, f64)>) -> Self {
+        sales.sort_by_key(|s| s.0);
+        let mut total = 0f64;
+        let cumulative = std::iter::once(0f64)
+            .chain(sales.iter().map(|s| {
+                total += s.1;
+                total
+            }))
+            .collect();
+
+        Self {
+            timestamps: sales.into_iter().map(|s| s.0).collect(),
+            cumulative,
+        }
+    }
+
+    fn at(&self, ts: &NaiveDateTime) -> Option<f64> {
+        let end = self.timestamps.partition_point(|t| *t < ts.timestamp());
+        let start = self
+            .timestamps
+            .partition_point(|t| *t <= (*ts - Duration::days(FALLBACK_AVG_DAYS)).timestamp());
+        if end <= start {
+            return None;
+        }
+
+        Some((self.cumulative[end] - self.cumulative[start]) / (end - start) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_episodes() {
+        let listing =
+            |update_type: &str, token_id: i32, price: Option<f64>, timestamp: i32| Listing {
+                collection_slug: String::from("test"),
+                update_type: update_type.to_string(),
+                token_id,
+                timestamp,
+                price: price.map(|p| p * 1e18),
+            };
+        let listings = vec![
+            listing("created", 1, Some(2.0), 0),
+            listing("created", 2, Some(1.0), 10),
+            listing("created", 1, Some(1.5), 20),
+            listing("successful", 1, None, 30),
+            listing("cancelled", 2, None, 40),
+            listing("created", 2, Some(1.2), 50),
+        ];
+
+        let episodes = get_listing_episodes(&listings);
+
+        assert_eq!(episodes.len(), 3);
+        assert_eq!(episodes[0].prices.len(), 2);
+        assert_eq!(episodes[0].outcome, EpisodeOutcome::Sold);
+        assert_eq!(episodes[1].outcome, EpisodeOutcome::Cancelled);
+        assert_eq!(episodes[2].outcome, EpisodeOutcome::Open);
+
+        let segment = |price_to_fair_value: f64, days: f64, sold: bool| ListingSegment {
+            token_id: 1,
+            price_to_fair_value,
+            days,
+            sold,
+        };
+        let segments = [
+            segment(1.0, 10.0, true),
+            segment(1.1, 20.0, false),
+            segment(1.0, 6.0, true),
+            segment(2.0, 50.0, false),
+        ];
+
+        let estimate = estimate_days_to_sale(segments.iter(), 1.0);
+        assert_eq!(estimate.expected_days, Some(18.0));
+        assert_eq!(estimate.nr_listings, 3);
+        assert_eq!(
+            estimate_days_to_sale(segments.iter(), 2.0).expected_days,
+            None
+        );
+    }
+}
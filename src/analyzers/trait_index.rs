@@ -0,0 +1,254 @@
+use crate::analyzers::wash_trades::median;
+use crate::from_wei;
+use crate::storage::read::{
+    read_asset_traits_for_collection, read_first_trait_sale_ts, read_listed_for_collection_at_ts,
+    read_listings_for_collection_after_ts, read_sales_for_collection_after_ts,
+    read_trait_index_start,
+};
+use crate::storage::write::write_trait_index;
+use crate::storage::{Listing, SaleEvent, TraitIndex};
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime, Utc};
+use sqlx::PgConnection;
+use std::collections::{BTreeMap, HashMap};
+
+static DAY: i64 = 86_400;
+
+/// Rebuilds the daily index of all traits of the collection for the days starting at or after
+/// `since`, from the tokens listed at the first of them and the later events. Returns the number
+/// of stored rows
+pub async fn build_trait_index(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    since: &NaiveDateTime,
+) -> Result<usize> {
+    let token_traits = read_asset_traits_for_collection(conn, collection_slug).await?;
+
+    let from = since.timestamp() + (DAY - since.timestamp().rem_euclid(DAY)) % DAY;
+    let from_ts = NaiveDateTime::from_timestamp(from, 0);
+
+    let listed = read_listed_for_collection_at_ts(conn, collection_slug, &from_ts)
+        .await?
+        .into_iter()
+        .filter_map(|l| l.price.map(|p| (l.token_id, from_wei(p))))
+        .collect::<HashMap<_, _>>();
+    let mut sales = read_sales_for_collection_after_ts(
+        conn,
+        collection_slug,
+        &(from_ts - Duration::seconds(1)),
+    )
+    .await?;
+    sales.sort_by_key(|s| s.timestamp);
+    let listings = read_listings_for_collection_after_ts(conn, collection_slug, &from_ts).await?;
+
+    let index = get_trait_index(
+        collection_slug,
+        &token_traits,
+        listed,
+        &sales,
+        &listings,
+        from,
+        Utc::now().timestamp(),
+    );
+    write_trait_index(conn, collection_slug, from as i32, &index).await?;
+
+    Ok(index.len())
+}
+
+/// Where a rebuild of the index has to start, `recent` unless the index misses earlier sales,
+/// for example of collections added before the index existed, then from the first sale
+pub async fn get_trait_index_since(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    recent: &NaiveDateTime,
+) -> Result<NaiveDateTime> {
+    let index_start = read_trait_index_start(conn, collection_slug).await?;
+    let first_sale = read_first_trait_sale_ts(conn, collection_slug).await?;
+
+    if misses_sales(index_start, first_sale) {
+        Ok(NaiveDateTime::from_timestamp(0, 0))
+    } else {
+        Ok(*recent)
+    }
+}
+
+fn misses_sales(index_start: Option<i32>, first_sale: Option<i32>) -> bool {
+    match (index_start, first_sale) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(start), Some(sale)) => start > sale - sale.rem_euclid(DAY as i32),
+    }
+}
+
+/// Index rows for every day from `from`, a day start, up to `until`, days on which a trait has
+/// neither a listing nor a sale are left out. `listed` are the token prices listed at `from`,
+/// `sales` and `listings` the later events sorted by time
+fn get_trait_index(
+    collection_slug: &str,
+    token_traits: &HashMap<i32, Vec<String>>,
+    mut listed: HashMap<i32, f64>,
+    sales: &[SaleEvent],
+    listings: &[Listing],
+    from: i64,
+    until: i64,
+) -> Vec<TraitIndex> {
+    // without listed tokens there is nothing to index before the first event
+    let first = if listed.is_empty() {
+        let first_event = match (sales.first(), listings.first()) {
+            (Some(s), Some(l)) => s.timestamp.min(l.timestamp),
+            (Some(s), None) => s.timestamp,
+            (None, Some(l)) => l.timestamp,
+            (None, None) => return vec![],
+        };
+        first_event as i64
+    } else {
+        from
+    };
+
+    let mut index = vec![];
+    let (mut next_sale, mut next_listing) = (0, 0);
+
+    let mut day = first - first.rem_euclid(DAY);
+    while day <= until {
+        let end = day + DAY;
+
+        // only the latest update of a token counts, updates without a price end the listing
+        while next_listing < listings.len() && (listings[next_listing].timestamp as i64) < end {
+            let l = &listings[next_listing];
+            match l.price {
+                Some(p) => listed.insert(l.token_id, from_wei(p)),
+                None => listed.remove(&l.token_id),
+            };
+            next_listing += 1;
+        }
+
+        let mut floors = BTreeMap::<&str, f64>::new();
+        for (token_id, price) in listed.iter() {
+            for t in token_traits.get(token_id).into_iter().flatten() {
+                let floor = floors.entry(t).or_insert(*price);
+                *floor = floor.min(*price);
+            }
+        }
+
+        let mut day_sales = BTreeMap::<&str, Vec<f64>>::new();
+        while next_sale < sales.len() && (sales[next_sale].timestamp as i64) < end {
+            let s = &sales[next_sale];
+            for t in token_traits.get(&s.token_id).into_iter().flatten() {
+                day_sales.entry(t).or_default().push(from_wei(s.price));
+            }
+            next_sale += 1;
+        }
+
+        let mut trait_ids = floors.keys().chain(day_sales.keys()).collect::<Vec<_>>();
+        trait_ids.sort_unstable();
+        trait_ids.dedup();
+
+        for trait_id in trait_ids {
+            let prices = day_sales.get(trait_id).cloned().unwrap_or_default();
+            let volume = prices.iter().sum::<f64>();
+
+            index.push(TraitIndex {
+                collection_slug: collection_slug.to_string(),
+                trait_id: trait_id.to_string(),
+                timestamp: day as i32,
+                floor_price: floors.get(trait_id).copied(),
+                avg_price: if prices.is_empty() {
+                    None
+                } else {
+                    Some(volume / prices.len() as f64)
+                },
+                nr_sales: prices.len() as i32,
+                median_price: median(prices),
+                volume,
+            });
+        }
+
+        day = end;
+    }
+
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sale(token_id: i32, timestamp: i32, price: f64) -> SaleEvent {
+        SaleEvent {
+            collection_slug: "c".to_string(),
+            token_id,
+            timestamp,
+            price: price * 1e18,
+            buyer: None,
+            seller: None,
+            flag: None,
+            payment_token: "ETH".to_string(),
+            payment_amount: price * 1e18,
+            payment_decimals: 18,
+        }
+    }
+
+    fn listing(token_id: i32, timestamp: i32, price: Option<f64>) -> Listing {
+        Listing {
+            collection_slug: "c".to_string(),
+            update_type: "created".to_string(),
+            token_id,
+            timestamp,
+            price: price.map(|p| p * 1e18),
+        }
+    }
+
+    #[test]
+    fn test_get_trait_index() {
+        let token_traits = HashMap::from([
+            (1, vec!["hat:red".to_string()]),
+            (2, vec!["hat:red".to_string()]),
+        ]);
+        let sales = vec![sale(1, 100, 1.0), sale(2, 200, 3.0), sale(1, 86_500, 2.0)];
+        let listings = vec![
+            listing(1, 50, Some(4.0)),
+            listing(2, 60, Some(5.0)),
+            listing(1, 86_450, None),
+        ];
+
+        let index = get_trait_index(
+            "c",
+            &token_traits,
+            HashMap::new(),
+            &sales,
+            &listings,
+            0,
+            86_600,
+        );
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[0].floor_price, Some(4.0));
+        assert_eq!(index[0].nr_sales, 2);
+        assert_eq!(index[0].median_price, Some(2.0));
+        assert_eq!(index[0].volume, 4.0);
+        assert_eq!(index[1].timestamp, 86_400);
+        assert_eq!(index[1].floor_price, Some(5.0));
+        assert_eq!(index[1].avg_price, Some(2.0));
+
+        // starting from the listed tokens of the second day gives the same row
+        let incremental = get_trait_index(
+            "c",
+            &token_traits,
+            HashMap::from([(1, 4.0), (2, 5.0)]),
+            &sales[2..],
+            &listings[2..],
+            86_400,
+            86_600,
+        );
+        assert_eq!(incremental, index[1..]);
+    }
+
+    #[test]
+    fn test_misses_sales() {
+        assert!(!misses_sales(None, None));
+        assert!(!misses_sales(Some(DAY as i32), None));
+        assert!(misses_sales(None, Some(100)));
+        assert!(!misses_sales(Some(0), Some(100)));
+        assert!(misses_sales(Some(DAY as i32), Some(100)));
+    }
+}
@@ -1,35 +1,101 @@
-use crate::analyzers::{prices::get_most_valued_trait_floor, rarities::get_trait_rarities};
 use crate::custom::read_custom_price;
 use crate::opensea::{os_client::OpenseaAPIClient, types::AssetsRequest};
 use crate::profiles::price_profile::PriceProfile;
-use crate::storage::read::read_collection;
+use crate::storage::read::{read_collection, read_nr_assets, read_token_ids_for_owner};
+use crate::storage::Collection;
 use anyhow::Result;
 use cached::proc_macro::cached;
+use chrono::{Duration, NaiveDateTime, Utc};
 use futures::StreamExt;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use std::collections::HashMap;
 
-pub async fn get_value_for_wallet(
-    pool: PgPool,
-    collection_slug: &str,
+/// Owners synced longer ago than this are stale and the wallet is read from OpenSea instead
+static MAX_OWNERS_AGE_HOURS: i64 = 2;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnershipSource {
+    Database,
+    Opensea,
+}
+
+/// Where the tokens held by a wallet were read from
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct Ownership {
+    pub source: OwnershipSource,
+    /// The tokens held are current as of this UTC time
+    pub as_of: NaiveDateTime,
+}
+
+/// Token ids of the collection held by `wallet`, lowest first. They are read from the stored
+/// asset owners if all assets are stored and transfers were synced recently, else from OpenSea
+pub async fn get_wallet_token_ids(
+    conn: &mut PgConnection,
+    collection: &Collection,
     wallet: &str,
-    limit: i64,
-    offset: i64,
-) -> Result<(f64, f64, f64, String, HashMap<String, PriceProfile>, usize)> {
-    let client = OpenseaAPIClient::new(2);
-    let mut conn = pool.acquire().await?;
-    let collection = read_collection(&mut conn, collection_slug).await?;
+) -> Result<(Vec<i32>, Ownership)> {
+    let now = Utc::now().naive_utc();
+
+    if let Some(synced_at) = collection
+        .owners_synced_at
+        .map(|t| NaiveDateTime::from_timestamp(t as i64, 0))
+    {
+        if now - synced_at < Duration::hours(MAX_OWNERS_AGE_HOURS)
+            && read_nr_assets(conn, &collection.slug).await? >= collection.total_supply as i64
+        {
+            let ids = read_token_ids_for_owner(conn, &collection.slug, wallet).await?;
+            return Ok((
+                ids,
+                Ownership {
+                    source: OwnershipSource::Database,
+                    as_of: synced_at,
+                },
+            ));
+        }
+    }
 
+    let client = OpenseaAPIClient::new(2);
     let req = AssetsRequest::new()
         .asset_contract_address(&collection.address)
         .owner(wallet)
         .build();
 
-    let assets = client.get_assets(req).await?;
+    let mut ids = client
+        .get_assets(req)
+        .await?
+        .into_iter()
+        .map(|a| a.token_id)
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
 
-    let mut ids = assets.into_iter().map(|a| a.token_id).collect::<Vec<_>>();
+    Ok((
+        ids,
+        Ownership {
+            source: OwnershipSource::Opensea,
+            as_of: now,
+        },
+    ))
+}
 
-    ids.sort_unstable();
+/// Prices the page of `ids` selected by `limit` and `offset`, `ids` sorted lowest first. The
+/// values only sum the priced tokens, the ids of those that failed to price are returned last
+pub async fn get_value_for_wallet(
+    pool: PgPool,
+    collection: &Collection,
+    ids: Vec<i32>,
+    limit: i64,
+    offset: i64,
+) -> Result<(
+    f64,
+    f64,
+    f64,
+    String,
+    HashMap<String, PriceProfile>,
+    usize,
+    Vec<i32>,
+)> {
+    let collection_slug = collection.slug.as_str();
     let total_tokens = ids.len();
 
     let ids_to_take = ids
@@ -42,20 +108,17 @@ pub async fn get_value_for_wallet(
     let mut value_min = 0f64;
     let mut value_avg = 0f64;
     let mut map = HashMap::<String, PriceProfile>::new();
-    let mut stream = futures::stream::iter(0..ids_to_take.len())
-        .map(|i| {
-            _get_profile(
-                pool.clone(),
-                collection_slug,
-                ids_to_take[i],
-                collection.rarity_cutoff,
-            )
+    let mut stream = futures::stream::iter(ids_to_take)
+        .map(|id| {
+            let pool = pool.clone();
+            async move { (id, _get_profile(pool, collection_slug, id).await) }
         })
         .buffer_unordered(6);
 
     let mut results = vec![];
+    let mut failed_ids = vec![];
 
-    while let Some(result) = stream.next().await {
+    while let Some((id, result)) = stream.next().await {
         match result {
             Ok(resp) => {
                 if let Some(r) = resp {
@@ -63,10 +126,12 @@ pub async fn get_value_for_wallet(
                 }
             }
             Err(e) => {
-                println!("Error: {:?}", e);
+                log::info!("Error pricing token {}: {}", id, e);
+                failed_ids.push(id);
             }
         }
     }
+    failed_ids.sort_unstable();
 
     for profile in results {
         value_max += profile.1.max_price;
@@ -83,6 +148,7 @@ pub async fn get_value_for_wallet(
         collection.address.clone(),
         map,
         total_tokens,
+        failed_ids,
     ))
 }
 
@@ -90,50 +156,27 @@ pub async fn get_value_for_wallet(
     size = 10_000,
     result = true,
     key = "String",
-    convert = r#"{ format!("{}{}", collection_slug, token_id) }"#
+    convert = r#"{ format!("{}:{}", collection_slug, token_id) }"#
 )]
 async fn _get_profile(
     pool: PgPool,
     collection_slug: &str,
     token_id: i32,
-    cutoff: f64,
 ) -> Result<Option<(i32, PriceProfile)>> {
-    // if there is a custom price short-circuit
-    if let Some(price) = read_custom_price(collection_slug, token_id)? {
-        let p = PriceProfile {
-            max_price: price,
-            min_price: price,
-            avg_price: price,
-            ..Default::default()
-        };
-
-        return Ok(Some((token_id, p)));
-    }
-
     let mut conn = pool.acquire().await?;
-    let token_traits = get_trait_rarities(&mut conn, collection_slug, token_id).await?;
+    let collection = read_collection(&mut conn, collection_slug).await?;
 
-    if token_traits.is_empty() {
-        return Ok(None);
+    // if there is a custom price short-circuit
+    if let Some(price) = read_custom_price(collection_slug, token_id)? {
+        return Ok(Some((
+            token_id,
+            PriceProfile::from_custom_price(price, &collection),
+        )));
     }
 
-    let rarest_trait = token_traits[0].trait_id.clone();
-
-    let most_valuable_trait =
-        get_most_valued_trait_floor(&mut conn, collection_slug, token_traits.clone(), cutoff)
-            .await?;
-
-    let profile = PriceProfile::make(
-        &mut conn,
-        collection_slug,
-        token_id as i32,
-        token_traits,
-        &rarest_trait,
-        &most_valuable_trait,
-        cutoff,
+    Ok(
+        PriceProfile::make_for_token(&mut conn, &collection, token_id, None)
+            .await?
+            .map(|p| (token_id, p)),
     )
-    .await
-    .unwrap();
-
-    Ok(Some((token_id, profile)))
 }
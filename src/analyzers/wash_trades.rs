@@ -0,0 +1,245 @@
+use crate::storage::read::{read_all_sales_for_collection, read_asset_traits_for_collection};
+use crate::storage::write::write_sale_flags;
+use crate::storage::SaleEvent;
+use anyhow::Result;
+use chrono::Duration;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+pub static SELF_TRADE: &str = "self_trade";
+pub static REPEATED_PAIR: &str = "repeated_pair";
+pub static BOUNCED: &str = "bounced";
+pub static OUTLIER: &str = "outlier";
+
+/// A wallet pair trading more often than this within a collection is washing
+static MAX_PAIR_TRADES: usize = 2;
+
+/// A token returning to a previous seller within this window was bounced between wallets
+static BOUNCE_WINDOW_DAYS: i64 = 30;
+
+/// Window of the collection median that sale prices are compared to
+static MARKET_WINDOW_DAYS: i64 = 30;
+
+/// Traits with fewer sales don't have a distribution to compare against
+static MIN_TRAIT_SALES: usize = 5;
+
+/// Scaled median absolute deviations a sale needs to be away from all of its trait medians
+static MAX_DEVIATIONS: f64 = 5.0;
+
+/// Lower bound of the deviation in log price, for traits whose sales were all alike
+static MIN_LOG_DEVIATION: f64 = 0.1;
+
+/// Flags the wash trades and outliers of a collection, returns the number of flagged sales
+pub async fn classify_sales(conn: &mut PgConnection, collection_slug: &str) -> Result<usize> {
+    let sales = read_all_sales_for_collection(conn, collection_slug).await?;
+    let asset_traits = read_asset_traits_for_collection(conn, collection_slug).await?;
+
+    let flags = get_sale_flags(&sales, &asset_traits);
+    write_sale_flags(conn, collection_slug, &flags).await?;
+
+    log::info!(
+        "Flagged {}/{} sales of {}",
+        flags.len(),
+        sales.len(),
+        collection_slug
+    );
+
+    Ok(flags.len())
+}
+
+/// Flag per (token_id, timestamp), wallet based flags take precedence over outliers
+pub fn get_sale_flags(
+    sales: &[SaleEvent],
+    asset_traits: &HashMap<i32, Vec<String>>,
+) -> HashMap<(i32, i32), &'static str> {
+    let mut sales = sales.iter().collect::<Vec<_>>();
+    sales.sort_by_key(|s| s.timestamp);
+
+    let mut flags = HashMap::new();
+
+    // Repeated buyer/seller pairs, in either direction, counting only the trades up to each sale
+    let mut pairs = HashMap::<(&str, &str), Vec<i32>>::new();
+    for s in &sales {
+        if let (Some(buyer), Some(seller)) = (&s.buyer, &s.seller) {
+            if buyer == seller {
+                flags.insert(key(s), SELF_TRADE);
+            } else {
+                pairs
+                    .entry(pair(buyer, seller))
+                    .or_default()
+                    .push(s.timestamp);
+            }
+        }
+    }
+    for s in &sales {
+        if let (Some(buyer), Some(seller)) = (&s.buyer, &s.seller) {
+            if buyer == seller {
+                continue;
+            }
+            let trades = pairs[&pair(buyer, seller)].partition_point(|t| *t <= s.timestamp);
+            if trades > MAX_PAIR_TRADES {
+                flags.entry(key(s)).or_insert(REPEATED_PAIR);
+            }
+        }
+    }
+
+    // Tokens bought back by one of their previous sellers
+    let mut token_sales = HashMap::<i32, Vec<&SaleEvent>>::new();
+    for s in &sales {
+        token_sales.entry(s.token_id).or_default().push(s);
+    }
+    let bounce_window = Duration::days(BOUNCE_WINDOW_DAYS).num_seconds() as i32;
+    for history in token_sales.values() {
+        for (j, later) in history.iter().enumerate() {
+            let buyer = match &later.buyer {
+                Some(b) => b,
+                None => continue,
+            };
+            if let Some(i) = history[..j].iter().position(|s| {
+                s.seller.as_ref() == Some(buyer) && later.timestamp - s.timestamp < bounce_window
+            }) {
+                for s in &history[i..=j] {
+                    flags.entry(key(s)).or_insert(BOUNCED);
+                }
+            }
+        }
+    }
+
+    // Prices far outside the distribution of every trait of the token
+    let clean = sales
+        .into_iter()
+        .filter(|s| s.price > 0f64 && !flags.contains_key(&key(s)))
+        .collect::<Vec<_>>();
+    let timestamps = clean.iter().map(|s| s.timestamp).collect::<Vec<_>>();
+    let market_window = Duration::days(MARKET_WINDOW_DAYS).num_seconds() as i32;
+    let day = Duration::days(1).num_seconds() as i32;
+
+    // log price relative to the collection median of the preceding window
+    let mut market = HashMap::<i32, Option<f64>>::new();
+    let relative = clean
+        .iter()
+        .filter_map(|s| {
+            let day_start = s.timestamp - s.timestamp % day;
+            let m = *market.entry(day_start).or_insert_with(|| {
+                let from = timestamps.partition_point(|t| *t < day_start - market_window);
+                let to = timestamps.partition_point(|t| *t < day_start);
+                median(clean[from..to].iter().map(|s| s.price.ln()).collect())
+            });
+            m.map(|m| (*s, s.price.ln() - m))
+        })
+        .collect::<Vec<_>>();
+
+    let mut trait_relative = HashMap::<&str, Vec<f64>>::new();
+    for (s, r) in &relative {
+        for t in asset_traits.get(&s.token_id).into_iter().flatten() {
+            trait_relative.entry(t.as_str()).or_default().push(*r);
+        }
+    }
+    let trait_stats = trait_relative
+        .into_iter()
+        .filter(|(_, r)| r.len() >= MIN_TRAIT_SALES)
+        .filter_map(|(t, r)| {
+            let m = median(r.clone())?;
+            let mad = median(r.iter().map(|v| (v - m).abs()).collect())? * 1.4826;
+            Some((t, (m, mad.max(MIN_LOG_DEVIATION))))
+        })
+        .collect::<HashMap<_, _>>();
+
+    for (s, r) in &relative {
+        let stats = asset_traits
+            .get(&s.token_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|t| trait_stats.get(t.as_str()))
+            .collect::<Vec<_>>();
+        if !stats.is_empty()
+            && stats
+                .iter()
+                .all(|(m, mad)| (r - m).abs() > MAX_DEVIATIONS * mad)
+        {
+            flags.insert(key(s), OUTLIER);
+        }
+    }
+
+    flags
+}
+
+fn key(sale: &SaleEvent) -> (i32, i32) {
+    (sale.token_id, sale.timestamp)
+}
+
+fn pair<'a>(a: &'a str, b: &'a str) -> (&'a str, &'a str) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+pub(crate) fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        Some(values[mid])
+    } else {
+        Some((values[mid - 1] + values[mid]) / 2f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sale(token_id: i32, timestamp: i32, price: f64, buyer: &str, seller: &str) -> SaleEvent {
+        SaleEvent {
+            collection_slug: "test".to_string(),
+            token_id,
+            timestamp,
+            price,
+            buyer: Some(buyer.to_string()),
+            seller: Some(seller.to_string()),
+            flag: None,
+            payment_token: "ETH".to_string(),
+            payment_amount: price,
+            payment_decimals: 18,
+        }
+    }
+
+    #[test]
+    fn test_get_sale_flags() {
+        let day = 86_400;
+        let traits = (0..20)
+            .map(|i| (i, vec!["hat:red".to_string()]))
+            .collect::<HashMap<_, _>>();
+
+        // a healthy market of distinct wallets trading around 1 eth
+        let mut sales = (0..20)
+            .map(|i| {
+                let price = 1f64 + (i % 3) as f64 * 0.1;
+                sale(i, i * day, price, &format!("b{}", i), &format!("s{}", i))
+            })
+            .collect::<Vec<_>>();
+
+        sales.push(sale(1, 21 * day, 1f64, "x", "x"));
+        sales.push(sale(2, 22 * day, 1f64, "y", "z"));
+        sales.push(sale(3, 23 * day, 1f64, "z", "y"));
+        sales.push(sale(4, 24 * day, 1f64, "y", "z"));
+        sales.push(sale(5, 25 * day, 1f64, "p", "q"));
+        sales.push(sale(5, 26 * day, 1f64, "q", "p"));
+        sales.push(sale(6, 27 * day, 100f64, "r", "t"));
+
+        let flags = get_sale_flags(&sales, &traits);
+        assert_eq!(flags.len(), 5);
+        assert_eq!(flags[&(1, 21 * day)], SELF_TRADE);
+        assert_eq!(flags[&(4, 24 * day)], REPEATED_PAIR);
+        // earlier trades of the pair are not flagged by later ones
+        assert!(!flags.contains_key(&(2, 22 * day)));
+        assert!(!flags.contains_key(&(3, 23 * day)));
+        assert_eq!(flags[&(5, 25 * day)], BOUNCED);
+        assert_eq!(flags[&(5, 26 * day)], BOUNCED);
+        assert_eq!(flags[&(6, 27 * day)], OUTLIER);
+    }
+}
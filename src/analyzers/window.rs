@@ -0,0 +1,212 @@
+use crate::storage::Collection;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDateTime};
+use rweb::openapi::{ComponentDescriptor, ComponentOrInlineSchema, Entity};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::ser::{Error as _, SerializeMap};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest window a profile can be requested for
+pub static MAX_WINDOW_DAYS: i64 = 730;
+
+/// Lookback of the windowed fields of a profile, written as a number of days like `7d`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Window {
+    pub days: i64,
+}
+
+impl Window {
+    pub fn days(days: i64) -> Self {
+        Self { days }
+    }
+
+    /// The requested window, else the collection's analysis window, else `default_days`
+    pub fn resolve(requested: Option<Window>, collection: &Collection, default_days: i64) -> Self {
+        requested
+            .or_else(|| {
+                collection
+                    .analysis_window_days
+                    .map(|d| Self::days(d as i64))
+            })
+            .unwrap_or_else(|| Self::days(default_days))
+    }
+
+    pub fn start(&self, ts: &NaiveDateTime) -> NaiveDateTime {
+        *ts - Duration::days(self.days)
+    }
+}
+
+impl FromStr for Window {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let days = s
+            .strip_suffix('d')
+            .and_then(|d| d.parse::<i64>().ok())
+            .ok_or_else(|| anyhow!("window {} is not a number of days like 7d", s))?;
+        if !(1..=MAX_WINDOW_DAYS).contains(&days) {
+            return Err(anyhow!(
+                "window {} is not between 1d and {}d",
+                s,
+                MAX_WINDOW_DAYS
+            ));
+        }
+        Ok(Self::days(days))
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d", self.days)
+    }
+}
+
+impl Serialize for Window {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Window {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl Entity for Window {
+    fn type_name() -> Cow<'static, str> {
+        String::type_name()
+    }
+
+    fn describe(comp_d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        String::describe(comp_d)
+    }
+}
+
+/// Fields of `T` computed over `window`. Meant to be flattened into a profile, every field is
+/// serialized with the window as suffix, so `nr_sales` over 7 days becomes `nr_sales_7d`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Windowed<T> {
+    pub window: Window,
+    pub values: T,
+}
+
+impl<T> Windowed<T> {
+    pub fn new(window: Window, values: T) -> Self {
+        Self { window, values }
+    }
+}
+
+impl<T: Serialize> Serialize for Windowed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let values = match serde_json::to_value(&self.values).map_err(S::Error::custom)? {
+            serde_json::Value::Object(v) => v,
+            _ => return Err(S::Error::custom("windowed values must be a struct")),
+        };
+
+        let mut map = serializer.serialize_map(Some(values.len()))?;
+        for (k, v) in values {
+            map.serialize_entry(&format!("{}_{}", k, self.window), &v)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Windowed<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map = serde_json::Map::<String, serde_json::Value>::deserialize(deserializer)?;
+
+        let mut window = None;
+        let mut values = serde_json::Map::new();
+        for (k, v) in map {
+            let (name, w) = match k.rsplit_once('_').map(|(n, w)| (n, w.parse::<Window>())) {
+                Some((name, Ok(w))) => (name, w),
+                _ => continue,
+            };
+            if *window.get_or_insert(w) != w {
+                return Err(D::Error::custom("windowed fields have different windows"));
+            }
+            values.insert(name.to_string(), v);
+        }
+
+        Ok(Self {
+            window: window.ok_or_else(|| D::Error::custom("no windowed fields"))?,
+            values: serde_json::from_value(serde_json::Value::Object(values))
+                .map_err(D::Error::custom)?,
+        })
+    }
+}
+
+/// The window is only known per request, so the schema names the fields of `T` with a
+/// `_{window}` placeholder suffix, like `nr_sales_{window}`
+impl<T: Entity> Entity for Windowed<T> {
+    fn type_name() -> Cow<'static, str> {
+        Cow::Owned(format!("Windowed{}", T::type_name()))
+    }
+
+    fn describe(comp_d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        let values = T::describe(comp_d);
+        let mut schema = comp_d.get_unpack(&values).clone();
+
+        let suffixed = |name: &str| Cow::Owned(format!("{}_{{window}}", name));
+        schema.properties = schema
+            .properties
+            .into_iter()
+            .map(|(name, property)| (suffixed(&name), property))
+            .collect();
+        schema.required = schema.required.iter().map(|name| suffixed(name)).collect();
+        schema.description = Cow::Borrowed(
+            "Fields end in the window they are computed over instead of {window}, like _7d",
+        );
+
+        ComponentOrInlineSchema::Inline(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+    struct Sales {
+        nr_sales: usize,
+        avg_price: Option<f64>,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+    struct Profile {
+        floor_price: f64,
+        #[serde(flatten)]
+        sales: Windowed<Sales>,
+    }
+
+    #[test]
+    fn test_windowed_field_names() {
+        assert_eq!("7d".parse::<Window>().unwrap(), Window::days(7));
+        assert!("7".parse::<Window>().is_err());
+        assert!("0d".parse::<Window>().is_err());
+
+        let profile = Profile {
+            floor_price: 1.0,
+            sales: Windowed::new(
+                Window::days(7),
+                Sales {
+                    nr_sales: 3,
+                    avg_price: Some(1.5),
+                },
+            ),
+        };
+
+        let json = serde_json::to_value(&profile).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "floor_price": 1.0, "nr_sales_7d": 3, "avg_price_7d": 1.5 })
+        );
+        assert_eq!(serde_json::from_value::<Profile>(json).unwrap(), profile);
+    }
+}
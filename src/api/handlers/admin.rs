@@ -1,14 +1,23 @@
 use super::super::errors::{internal_error, ServiceError};
+use crate::analyzers::backtest::run_backtest;
+use crate::analyzers::hedonic::fit_hedonic_model;
 use crate::analyzers::rarities::get_collection_avg_trait_rarity;
+use crate::analyzers::rarity_scores::store_rarity_scores;
+use crate::analyzers::strategies::is_registered;
+use crate::analyzers::trait_index::build_trait_index;
+use crate::analyzers::wash_trades::classify_sales;
+use crate::analyzers::window::Window;
 use crate::opensea::types::AssetsRequest;
 use crate::opensea::{os_client::OpenseaAPIClient, types::Trait};
 use crate::storage::delete::*;
 use crate::storage::preprocess;
+use crate::storage::read::{read_backtest_results, read_collection};
 use crate::storage::write::*;
-use crate::storage::Trait as StorageTrait;
+use crate::storage::{BacktestResult, FxRate, Trait as StorageTrait};
 use crate::sync::sync_events::sync_collection;
 use anyhow::Result;
-use chrono::{Duration, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
+use itertools::Itertools;
 use rweb::*;
 use sqlx::{PgConnection, PgPool};
 use std::collections::HashSet;
@@ -20,6 +29,45 @@ pub struct NewCollectionBody {
     pub rarity_cutoff_multiplier: f64,
     pub ignored_trait_types_rarity: Vec<String>,
     pub ignored_trait_types_overlap: Vec<String>,
+    /// Sizes of the trait combinations to compute overlaps for, defaults to 3, 4 and 5
+    #[serde(default = "default_overlap_sizes")]
+    pub overlap_sizes: Vec<i32>,
+    /// Names of the pricing strategies to use, all strategies are used if empty
+    #[serde(default)]
+    pub pricing_strategies: Vec<String>,
+    /// Also use sales flagged as wash trades or outliers in the analyzers
+    #[serde(default)]
+    pub include_flagged_sales: bool,
+    /// Window of the windowed profile fields and price averages like `7d`, if not set every
+    /// field keeps its own default
+    pub analysis_window: Option<Window>,
+}
+
+/// Omitted settings keep their stored value
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct UpdateCollectionBody {
+    pub collection_slug: String,
+    pub rarity_cutoff_multiplier: f64,
+    pub ignored_trait_types_rarity: Option<Vec<String>>,
+    pub ignored_trait_types_overlap: Option<Vec<String>>,
+    /// Sizes of the trait combinations to compute overlaps for
+    pub overlap_sizes: Option<Vec<i32>>,
+    /// Names of the pricing strategies to use, all strategies are used if empty
+    pub pricing_strategies: Option<Vec<String>>,
+    /// Also use sales flagged as wash trades or outliers in the analyzers
+    pub include_flagged_sales: Option<bool>,
+    /// Window of the windowed profile fields and price averages like `7d`, null unsets it
+    #[serde(default, deserialize_with = "deserialize_set")]
+    pub analysis_window: Option<Option<Window>>,
+}
+
+/// Tells a field set to null, `Some(None)`, apart from an omitted one, `None`
+fn deserialize_set<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    serde::Deserialize::deserialize(deserializer).map(Some)
 }
 
 #[derive(serde::Deserialize, rweb::Schema)]
@@ -44,6 +92,8 @@ pub async fn new_collection(
     if key != dotenv::var("ADMIN_API_KEY").unwrap() {
         return Err(warp::reject::custom(ServiceError::Unauthorized));
     }
+    check_pricing_strategies(&req.pricing_strategies)?;
+    check_overlap_sizes(&req.overlap_sizes)?;
     tokio::task::spawn(_store_collection(
         pool,
         req.collection_slug.clone(),
@@ -54,10 +104,15 @@ pub async fn new_collection(
             .map(|t| t.to_lowercase())
             .collect(),
         req.ignored_trait_types_overlap,
+        req.overlap_sizes,
+        req.pricing_strategies,
+        req.include_flagged_sales,
+        req.analysis_window.map(|w| w.days as i32),
     ));
     Ok(().into())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn _store_collection(
     pool: PgPool,
     collection_slug: String,
@@ -65,6 +120,10 @@ async fn _store_collection(
     multiplier: f64,
     ignored_trait_types_rarity: Vec<String>,
     ignored_trait_types_overlap: Vec<String>,
+    overlap_sizes: Vec<i32>,
+    pricing_strategies: Vec<String>,
+    include_flagged_sales: bool,
+    analysis_window_days: Option<i32>,
 ) -> Result<()> {
     let client = OpenseaAPIClient::new(1);
     let collection = client.get_collection(&collection_slug).await?;
@@ -121,6 +180,10 @@ async fn _store_collection(
         multiplier,
         ignored_trait_types_rarity.clone(),
         ignored_trait_types_overlap.clone(),
+        overlap_sizes.clone(),
+        pricing_strategies,
+        include_flagged_sales,
+        analysis_window_days,
         None,
     )
     .await
@@ -136,20 +199,28 @@ async fn _store_collection(
         add_token_id_list(&mut conn, &collection_slug, &t, ids).await?;
     }
 
-    let processed = preprocess::process_assets(
+    let (processed, overlaps) = preprocess::process_assets(
         pool.clone(),
         all_assets.clone(),
         &collection_slug,
         ignored_trait_types_overlap,
+        &map,
+        &overlap_sizes,
     )
     .await?;
 
     for a in &processed {
         write_asset(&mut conn, a).await.unwrap();
     }
+    write_asset_overlaps(&mut conn, &collection_slug, &overlaps).await?;
 
     println!("  Stored {} assets!", all_assets.len());
 
+    println!("  Scoring rarities...");
+
+    let stored_collection = read_collection(&mut conn, &collection_slug).await?;
+    store_rarity_scores(&mut conn, &stored_collection).await?;
+
     println!("  Storing listings...");
 
     for a in &all_assets {
@@ -191,11 +262,62 @@ async fn _store_collection(
     .await
     .unwrap();
 
+    println!("  Flagging wash trades...");
+
+    classify_sales(&mut conn, &collection_slug).await?;
+
+    println!("  Building trait index...");
+
+    build_trait_index(
+        &mut conn,
+        &collection_slug,
+        &NaiveDateTime::from_timestamp(0, 0),
+    )
+    .await?;
+
+    println!("  Fitting hedonic model...");
+
+    fit_hedonic_model(&mut conn, &collection_slug, &Utc::now().naive_utc()).await?;
+
     println!("  Done");
 
     Ok(())
 }
 
+fn default_overlap_sizes() -> Vec<i32> {
+    preprocess::DEFAULT_OVERLAP_SIZES.to_vec()
+}
+
+fn check_overlap_sizes(overlap_sizes: &[i32]) -> Result<(), Rejection> {
+    if let Some(s) = overlap_sizes
+        .iter()
+        .find(|s| !(1..=preprocess::MAX_OVERLAP_SIZE).contains(*s))
+    {
+        return Err(warp::reject::custom(ServiceError::BadRequest(format!(
+            "overlap size {} is not between 1 and {}",
+            s,
+            preprocess::MAX_OVERLAP_SIZE
+        ))));
+    }
+    match overlap_sizes.iter().duplicates().next() {
+        Some(s) => Err(warp::reject::custom(ServiceError::BadRequest(format!(
+            "overlap size {} is listed more than once",
+            s
+        )))),
+        None => Ok(()),
+    }
+}
+
+fn check_pricing_strategies(pricing_strategies: &[String]) -> Result<(), Rejection> {
+    match pricing_strategies.iter().find(|s| !is_registered(s)) {
+        Some(s) => Err(warp::reject::custom(ServiceError::BadRequest(format!(
+            "unknown pricing strategy {}",
+            s
+        )))),
+        None => Ok(()),
+    }
+}
+
 #[post("/admin/collection_minimal/")]
 #[openapi(tags("Admin"))]
 #[openapi(summary = "Add Minimal collection Info")]
@@ -236,6 +358,10 @@ async fn _store_collection_minimal(
         0f64,
         vec![],
         vec![],
+        default_overlap_sizes(),
+        vec![],
+        false,
+        None,
         Some(address),
     )
     .await
@@ -253,32 +379,46 @@ async fn _store_collection_minimal(
 pub async fn update_collection(
     #[data] pool: PgPool,
     #[header = "x-api-key"] key: String,
-    body: rweb::Json<NewCollectionBody>,
+    body: rweb::Json<UpdateCollectionBody>,
 ) -> Result<Json<()>, Rejection> {
-    let req: NewCollectionBody = body.into_inner();
+    let req: UpdateCollectionBody = body.into_inner();
     println!("/update_collection/{}", req.collection_slug);
 
     if key != dotenv::var("ADMIN_API_KEY").unwrap() {
         return Err(warp::reject::custom(ServiceError::Unauthorized));
     }
-    tokio::task::spawn(_update_collection(
-        pool,
-        req.collection_slug.clone(),
-        req.rarity_cutoff_multiplier,
-        req.ignored_trait_types_rarity,
-        req.ignored_trait_types_overlap,
-    ));
+    if let Some(pricing_strategies) = &req.pricing_strategies {
+        check_pricing_strategies(pricing_strategies)?;
+    }
+    if let Some(overlap_sizes) = &req.overlap_sizes {
+        check_overlap_sizes(overlap_sizes)?;
+    }
+    tokio::task::spawn(_update_collection(pool, req));
     Ok(().into())
 }
 
-async fn _update_collection(
-    pool: PgPool,
-    collection_slug: String,
-    multiplier: f64,
-    ignored_trait_types_rarity: Vec<String>,
-    ignored_trait_types_overlap: Vec<String>,
-) -> Result<()> {
+async fn _update_collection(pool: PgPool, req: UpdateCollectionBody) -> Result<()> {
     let mut conn = pool.acquire().await?;
+    let stored = read_collection(&mut conn, &req.collection_slug).await?;
+
+    let collection_slug = req.collection_slug;
+    let multiplier = req.rarity_cutoff_multiplier;
+    let ignored_trait_types_rarity = req
+        .ignored_trait_types_rarity
+        .unwrap_or(stored.ignored_trait_types_rarity);
+    let ignored_trait_types_overlap = req
+        .ignored_trait_types_overlap
+        .unwrap_or(stored.ignored_trait_types_overlap);
+    let overlap_sizes = req.overlap_sizes.unwrap_or(stored.overlap_sizes);
+    let pricing_strategies = req.pricing_strategies.unwrap_or(stored.pricing_strategies);
+    let include_flagged_sales = req
+        .include_flagged_sales
+        .unwrap_or(stored.include_flagged_sales);
+    let analysis_window_days = match req.analysis_window {
+        Some(w) => w.map(|w| w.days as i32),
+        None => stored.analysis_window_days,
+    };
+
     let client = OpenseaAPIClient::new(1);
     let collection = client.get_collection(&collection_slug).await?;
 
@@ -319,7 +459,11 @@ async fn _update_collection(
         &collection.collection.slug,
         total_supply,
         ignored_trait_types_rarity,
-        ignored_trait_types_overlap,
+        ignored_trait_types_overlap.clone(),
+        overlap_sizes.clone(),
+        pricing_strategies,
+        include_flagged_sales,
+        analysis_window_days,
         (collection_avg_trait_rarity * multiplier) / total_supply,
     )
     .await
@@ -329,6 +473,27 @@ async fn _update_collection(
         .await
         .unwrap_or_default();
 
+    let map = preprocess::generate_token_mapping(all_assets.clone()).await?;
+    for (t, ids) in map.clone() {
+        add_token_id_list(&mut conn, &collection_slug, &t, ids).await?;
+    }
+
+    let (_, overlaps) = preprocess::process_assets(
+        pool.clone(),
+        all_assets,
+        &collection_slug,
+        ignored_trait_types_overlap,
+        &map,
+        &overlap_sizes,
+    )
+    .await?;
+    write_asset_overlaps(&mut conn, &collection_slug, &overlaps).await?;
+
+    let stored_collection = read_collection(&mut conn, &collection_slug).await?;
+    store_rarity_scores(&mut conn, &stored_collection).await?;
+
+    fit_hedonic_model(&mut conn, &collection_slug, &Utc::now().naive_utc()).await?;
+
     println!("Done updating!");
 
     Ok(())
@@ -364,3 +529,138 @@ async fn _delete_collection(conn: &mut PgConnection, collection_slug: &str) -> R
         .unwrap_or_default();
     Ok(())
 }
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct BacktestBody {
+    pub collection_slug: String,
+    /// Only replay sales of the last `days_back` days, all sales if not set
+    pub days_back: Option<i64>,
+    /// Only replay the most recent `max_sales` sales
+    pub max_sales: Option<usize>,
+}
+
+#[post("/admin/backtest/")]
+#[openapi(tags("Admin"))]
+#[openapi(summary = "Backtest the pricing strategies")]
+#[openapi(description = r#"
+Replays the stored sales of a collection against every pricing strategy and stores the errors
+"#)]
+pub async fn new_backtest(
+    #[data] pool: PgPool,
+    #[header = "x-api-key"] key: String,
+    body: rweb::Json<BacktestBody>,
+) -> Result<Json<()>, Rejection> {
+    let req: BacktestBody = body.into_inner();
+    println!("/new_backtest/{}", req.collection_slug);
+    if key != dotenv::var("ADMIN_API_KEY").unwrap() {
+        return Err(warp::reject::custom(ServiceError::Unauthorized));
+    }
+    tokio::task::spawn(_backtest_collection(
+        pool,
+        req.collection_slug,
+        req.days_back,
+        req.max_sales,
+    ));
+    Ok(().into())
+}
+
+async fn _backtest_collection(
+    pool: PgPool,
+    collection_slug: String,
+    days_back: Option<i64>,
+    max_sales: Option<usize>,
+) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    let since = match days_back {
+        Some(d) => (Utc::now() - Duration::days(d)).naive_utc(),
+        None => chrono::NaiveDateTime::from_timestamp(0, 0),
+    };
+
+    let results = run_backtest(&mut conn, &collection_slug, &since, max_sales).await?;
+    write_backtest_results(&mut conn, &results).await?;
+
+    println!("Done backtesting {}!", collection_slug);
+
+    Ok(())
+}
+
+#[get("/admin/backtest/{collection_slug}")]
+#[openapi(tags("Admin"))]
+#[openapi(summary = "Get backtest results")]
+#[openapi(description = r#"
+Gets the stored backtest errors per pricing strategy, most accurate first
+"#)]
+pub async fn get_backtest(
+    #[data] pool: PgPool,
+    #[header = "x-api-key"] key: String,
+    collection_slug: String,
+) -> Result<Json<Vec<BacktestResult>>, Rejection> {
+    println!("/get_backtest/{}", collection_slug);
+    if key != dotenv::var("ADMIN_API_KEY").unwrap() {
+        return Err(warp::reject::custom(ServiceError::Unauthorized));
+    }
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    read_backtest_results(&mut conn, &collection_slug)
+        .await
+        .map(|r| r.into())
+        .map_err(internal_error)
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct FxRatesBody {
+    pub rates: Vec<FxRate>,
+}
+
+#[post("/admin/fx_rates/")]
+#[openapi(tags("Admin"))]
+#[openapi(summary = "Import ETH/USD rates")]
+#[openapi(description = r#"
+Stores historical ETH/USD rates, existing rates with the same timestamp are overwritten
+"#)]
+pub async fn import_fx_rates(
+    #[data] pool: PgPool,
+    #[header = "x-api-key"] key: String,
+    body: rweb::Json<FxRatesBody>,
+) -> Result<Json<()>, Rejection> {
+    let req: FxRatesBody = body.into_inner();
+    println!("/import_fx_rates/{}", req.rates.len());
+    if key != dotenv::var("ADMIN_API_KEY").unwrap() {
+        return Err(warp::reject::custom(ServiceError::Unauthorized));
+    }
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    write_fx_rates(&mut conn, &req.rates)
+        .await
+        .map(|r| r.into())
+        .map_err(internal_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_collection_body_keeps_omitted() {
+        let req: UpdateCollectionBody = serde_json::from_value(serde_json::json!({
+            "collection_slug": "c",
+            "total_supply_expected": 10,
+            "rarity_cutoff_multiplier": 1.0,
+            "ignored_trait_types_rarity": [],
+            "ignored_trait_types_overlap": [],
+        }))
+        .unwrap();
+        assert!(req.overlap_sizes.is_none());
+        assert!(req.pricing_strategies.is_none());
+        assert!(req.include_flagged_sales.is_none());
+        assert!(req.analysis_window.is_none());
+
+        let req: UpdateCollectionBody = serde_json::from_value(serde_json::json!({
+            "collection_slug": "c",
+            "rarity_cutoff_multiplier": 1.0,
+            "analysis_window": null,
+        }))
+        .unwrap();
+        assert_eq!(req.analysis_window, Some(None));
+    }
+}
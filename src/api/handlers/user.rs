@@ -1,10 +1,20 @@
-use super::super::errors::internal_error;
-use crate::analyzers::prices::get_most_valued_trait_floor;
-use crate::analyzers::rarities::get_trait_rarities;
+use super::super::errors::{internal_error, ServiceError};
+use crate::analyzers::comps::{get_token_comps, Comps};
+use crate::analyzers::deals::{get_deals, DealFilter, Deals};
+use crate::analyzers::depth::{get_depth, Depth};
+use crate::analyzers::fx::{get_eth_rate, Currency, EthRates};
+use crate::analyzers::rarities::get_trait_set_rarities;
+use crate::analyzers::similar::{get_similar_tokens, SimilarTokens};
+use crate::analyzers::window::Window;
 use crate::custom::read_custom_price;
+use crate::profiles::appraisal_profile::AppraisalProfile;
 use crate::profiles::collection_profile::CollectionProfile;
+use crate::profiles::portfolio_profile::PortfolioProfile;
 use crate::profiles::price_profile::PriceProfile;
+use crate::profiles::rarity_profile::{RarityRanking, RarityScoreModel};
 use crate::profiles::token_profile::TokenProfile;
+use crate::profiles::trait_profile::{SortOrder, TraitHistory, TraitLadder, TraitSort};
+use crate::profiles::valuation_profile::ValuationProfile;
 use crate::profiles::wallet_profile::WalletProfile;
 use crate::storage::{
     read::{read_all_collections, read_collection},
@@ -12,9 +22,43 @@ use crate::storage::{
 };
 use anyhow::Result;
 use cached::proc_macro::cached;
+use chrono::{Duration, NaiveDateTime, Utc};
 use rweb::*;
 use sqlx::{PgConnection, PgPool};
 
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct AsOfRequest {
+    /// Reproduce the appraisal as it would have been at this UTC time, defaults to now
+    pub as_of: Option<NaiveDateTime>,
+    /// Currency of all prices, `as_of` appraisals use the rate at that time
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct CurrencyRequest {
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct ProfileRequest {
+    /// Reproduce the profile as it would have been at this UTC time, defaults to now
+    pub as_of: Option<NaiveDateTime>,
+    /// Window of the windowed fields like `7d`, defaults to the collection's settings
+    pub window: Option<Window>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct WindowRequest {
+    /// Window of the windowed fields like `7d`, defaults to the collection's settings
+    pub window: Option<Window>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
 #[get("/profile/{collection_slug}/{token_id}")]
 #[openapi(tags("Token"))]
 #[openapi(summary = "Get a profile for token")]
@@ -25,30 +69,41 @@ pub async fn get_profile(
     #[data] pool: PgPool,
     token_id: i32,
     collection_slug: String,
+    query: rweb::Query<ProfileRequest>,
 ) -> Result<Json<TokenProfile>, Rejection> {
-    println!("/get_profile/{}/{}", collection_slug, token_id);
+    let req: ProfileRequest = query.into_inner();
+    println!(
+        "/get_profile/{}/{}/{:?}",
+        collection_slug, token_id, req.as_of
+    );
     let mut conn = pool.acquire().await.map_err(internal_error)?;
 
-    _get_profile(&mut conn, collection_slug, token_id)
+    let profile = _get_profile(&mut conn, collection_slug, token_id, req.window, req.as_of)
         .await
-        .map(|r| r.into())
-        .map_err(internal_error)
+        .map_err(internal_error)?;
+    let rates = EthRates::load(&mut conn, req.currency)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(profile.in_currency(&rates, req.as_of.as_ref()).into())
 }
 
 #[cached(
     size = 10,
     result = true,
     key = "String",
-    convert = r#"{ format!("{}{}", collection_slug, token_id) }"#
+    convert = r#"{ format!("{}:{}:{:?}:{:?}", collection_slug, token_id, window, as_of) }"#
 )]
 async fn _get_profile(
     conn: &mut PgConnection,
     collection_slug: String,
     token_id: i32,
+    window: Option<Window>,
+    as_of: Option<NaiveDateTime>,
 ) -> Result<TokenProfile> {
     let collection = read_collection(conn, &collection_slug).await?;
 
-    TokenProfile::make(conn, collection, token_id).await
+    TokenProfile::make(conn, collection, token_id, window, as_of).await
 }
 
 #[get("/price/{collection_slug}/{token_id}")]
@@ -61,65 +116,151 @@ pub async fn get_price_profile(
     #[data] pool: PgPool,
     token_id: i32,
     collection_slug: String,
+    query: rweb::Query<AsOfRequest>,
 ) -> Result<Json<PriceProfile>, Rejection> {
-    println!("/get_price_profile/{}/{}", collection_slug, token_id);
+    let req: AsOfRequest = query.into_inner();
+    println!(
+        "/get_price_profile/{}/{}/{:?}",
+        collection_slug, token_id, req.as_of
+    );
     let mut conn = pool.acquire().await.map_err(internal_error)?;
 
-    _get_price_profile(&mut conn, collection_slug, token_id)
+    let profile = _get_price_profile(&mut conn, collection_slug, token_id, req.as_of)
         .await
-        .map(|r| r.into())
-        .map_err(internal_error)
+        .map_err(internal_error)?;
+    let rate = get_eth_rate(&mut conn, req.currency, req.as_of.as_ref())
+        .await
+        .map_err(internal_error)?;
+
+    Ok(profile.in_currency(req.currency, rate).into())
 }
 
 #[cached(
     size = 100,
     result = true,
     key = "String",
-    convert = r#"{ format!("{}{}", collection_slug, token_id) }"#
+    convert = r#"{ format!("{}:{}:{:?}", collection_slug, token_id, as_of) }"#
 )]
 async fn _get_price_profile(
     conn: &mut PgConnection,
     collection_slug: String,
     token_id: i32,
+    as_of: Option<NaiveDateTime>,
 ) -> Result<PriceProfile> {
+    let collection = read_collection(conn, &collection_slug).await?;
+
     // if there is a custom price short-circuit
     if let Some(price) = read_custom_price(&collection_slug, token_id)? {
-        let p = PriceProfile {
-            max_price: price,
-            min_price: price,
-            avg_price: price,
-            ..Default::default()
-        };
-
-        return Ok(p);
+        return Ok(PriceProfile::from_custom_price(price, &collection));
     }
-    let collection = read_collection(conn, &collection_slug).await?;
 
-    let token_traits = get_trait_rarities(conn, &collection_slug, token_id).await?;
+    Ok(
+        PriceProfile::make_for_token(conn, &collection, token_id, as_of)
+            .await?
+            .unwrap_or_default(),
+    )
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct HistoryRequest {
+    /// Only return the valuations of the last `days_back` days, all of them if not set
+    pub days_back: Option<i64>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[get("/price/{collection_slug}/{token_id}/history")]
+#[openapi(tags("Token"))]
+#[openapi(summary = "Get valuation history for token")]
+#[openapi(description = r#"
+    Returns the stored daily valuations of the token, oldest first
+"#)]
+pub async fn get_valuation_history(
+    #[data] pool: PgPool,
+    token_id: i32,
+    collection_slug: String,
+    query: rweb::Query<HistoryRequest>,
+) -> Result<Json<ValuationProfile>, Rejection> {
+    let req: HistoryRequest = query.into_inner();
+    println!(
+        "/get_valuation_history/{}/{}/{:?}",
+        collection_slug, token_id, req.days_back
+    );
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    let since = match req.days_back {
+        Some(d) => (Utc::now() - Duration::days(d)).naive_utc(),
+        None => NaiveDateTime::from_timestamp(0, 0),
+    };
+
+    ValuationProfile::make(&mut conn, &collection_slug, token_id, &since, req.currency)
+        .await
+        .map(|r| r.into())
+        .map_err(internal_error)
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct AppraisalRequest {
+    /// Trait ids formatted as `trait_type:value`
+    pub traits: Vec<String>,
+    pub as_of: Option<NaiveDateTime>,
+    /// Window of the windowed fields like `7d`, defaults to the collection's settings
+    pub window: Option<Window>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[post("/appraise/{collection_slug}")]
+#[openapi(tags("Token"))]
+#[openapi(summary = "Appraise a set of traits")]
+#[openapi(description = r#"
+    Returns the pricing, liquidity and rarity of a hypothetical token with the given traits
+"#)]
+pub async fn get_appraisal(
+    #[data] pool: PgPool,
+    collection_slug: String,
+    body: rweb::Json<AppraisalRequest>,
+) -> Result<Json<AppraisalProfile>, Rejection> {
+    let req: AppraisalRequest = body.into_inner();
+    println!("/get_appraisal/{}/{:?}", collection_slug, req.traits);
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    let trait_ids = req
+        .traits
+        .iter()
+        .map(|t| t.to_lowercase())
+        .collect::<Vec<_>>();
+    let token_traits = get_trait_set_rarities(&mut conn, &collection_slug, &trait_ids)
+        .await
+        .map_err(internal_error)?;
+
+    if let Some(t) = trait_ids
+        .iter()
+        .find(|t| !token_traits.iter().any(|r| &r.trait_id == *t))
+    {
+        return Err(warp::reject::custom(ServiceError::BadRequest(format!(
+            "unknown trait {}",
+            t
+        ))));
+    }
     if token_traits.is_empty() {
-        return Ok(PriceProfile::default());
+        return Err(warp::reject::custom(ServiceError::BadRequest(
+            "no traits given".to_string(),
+        )));
     }
 
-    let rarest_trait = token_traits[0].trait_id.clone();
-
-    let most_valuable_trait = get_most_valued_trait_floor(
-        conn,
-        &collection_slug,
-        token_traits.clone(),
-        collection.rarity_cutoff,
-    )
-    .await?;
+    let collection = read_collection(&mut conn, &collection_slug)
+        .await
+        .map_err(internal_error)?;
+    let profile =
+        AppraisalProfile::make(&mut conn, collection, token_traits, req.window, req.as_of)
+            .await
+            .map_err(internal_error)?;
+    let rate = get_eth_rate(&mut conn, req.currency, req.as_of.as_ref())
+        .await
+        .map_err(internal_error)?;
 
-    PriceProfile::make(
-        conn,
-        &collection_slug.to_string(),
-        token_id,
-        token_traits,
-        &rarest_trait,
-        &most_valuable_trait,
-        collection.rarity_cutoff,
-    )
-    .await
+    Ok(profile.in_currency(req.currency, rate).into())
 }
 
 #[get("/collection/{collection_slug}")]
@@ -131,14 +272,21 @@ Gets the collection profile for given collection_slug
 pub async fn get_collection_profile(
     #[data] pool: PgPool,
     collection_slug: String,
+    query: rweb::Query<WindowRequest>,
 ) -> Result<Json<CollectionProfile>, Rejection> {
+    let req: WindowRequest = query.into_inner();
     println!("/get_collection/{}", collection_slug);
     let mut conn = pool.acquire().await.map_err(internal_error)?;
 
-    CollectionProfile::make(&mut conn, &collection_slug.to_string())
+    let profile =
+        CollectionProfile::make(&mut conn, &collection_slug.to_string(), req.window, None)
+            .await
+            .map_err(internal_error)?;
+    let rates = EthRates::load(&mut conn, req.currency)
         .await
-        .map(|r| r.into())
-        .map_err(internal_error)
+        .map_err(internal_error)?;
+
+    Ok(profile.in_currency(&rates, None).into())
 }
 
 #[get("/collection/")]
@@ -159,19 +307,299 @@ pub async fn get_all_collections(
         .map_err(internal_error)
 }
 
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct TraitLadderRequest {
+    #[serde(default)]
+    pub sort: TraitSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Window of the trait sales like `7d`, defaults to the collection's settings
+    pub window: Option<Window>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[get("/collection/{collection_slug}/traits")]
+#[openapi(tags("Collection"))]
+#[openapi(summary = "Get floor and sales of all traits")]
+#[openapi(description = r#"
+    Returns every trait of the collection with its rarity, current floor, number listed, last sale and sales over the window, 30 days by default
+"#)]
+pub async fn get_trait_ladder(
+    #[data] pool: PgPool,
+    collection_slug: String,
+    query: rweb::Query<TraitLadderRequest>,
+) -> Result<Json<TraitLadder>, Rejection> {
+    let req: TraitLadderRequest = query.into_inner();
+    println!("/get_trait_ladder/{}", collection_slug);
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    let rates = EthRates::load(&mut conn, req.currency)
+        .await
+        .map_err(internal_error)?;
+    let ladder = TraitLadder::make(
+        &mut conn,
+        &collection_slug,
+        req.sort,
+        req.order,
+        req.window,
+        &rates,
+        &Utc::now().naive_utc(),
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok(ladder.into())
+}
+
+#[get("/collection/{collection_slug}/traits/{trait_id}/history")]
+#[openapi(tags("Collection"))]
+#[openapi(summary = "Get daily index of trait")]
+#[openapi(description = r#"
+    Returns the daily floor, median sale price and volume of the trait, oldest first
+"#)]
+pub async fn get_trait_history(
+    #[data] pool: PgPool,
+    trait_id: String,
+    collection_slug: String,
+    query: rweb::Query<HistoryRequest>,
+) -> Result<Json<TraitHistory>, Rejection> {
+    let req: HistoryRequest = query.into_inner();
+    println!(
+        "/get_trait_history/{}/{}/{:?}",
+        collection_slug, trait_id, req.days_back
+    );
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    let since = match req.days_back {
+        Some(d) => (Utc::now() - Duration::days(d)).naive_utc(),
+        None => NaiveDateTime::from_timestamp(0, 0),
+    };
+
+    TraitHistory::make(&mut conn, &collection_slug, &trait_id, &since, req.currency)
+        .await
+        .map(|r| r.into())
+        .map_err(internal_error)
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct RankingRequest {
+    #[serde(default)]
+    pub model: RarityScoreModel,
+    /// Defaults to 100
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[get("/collection/{collection_slug}/ranking")]
+#[openapi(tags("Collection"))]
+#[openapi(summary = "Get rarity ranking of collection")]
+#[openapi(description = r#"
+    Returns the rarity scores of the assets, rarest first under the chosen model
+"#)]
+pub async fn get_rarity_ranking(
+    #[data] pool: PgPool,
+    collection_slug: String,
+    query: rweb::Query<RankingRequest>,
+) -> Result<Json<RarityRanking>, Rejection> {
+    let req: RankingRequest = query.into_inner();
+    println!("/get_rarity_ranking/{}/{:?}", collection_slug, req.model);
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    RarityRanking::make(
+        &mut conn,
+        &collection_slug,
+        req.model,
+        req.limit.unwrap_or(100),
+        req.offset.unwrap_or_default(),
+    )
+    .await
+    .map(|r| r.into())
+    .map_err(internal_error)
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct DepthRequest {
+    /// Only tokens having this trait
+    pub trait_id: Option<String>,
+    /// Simulate buying this many of the cheapest listings
+    pub sweep: Option<usize>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[get("/collection/{collection_slug}/depth")]
+#[openapi(tags("Collection"))]
+#[openapi(summary = "Get listing depth of collection")]
+#[openapi(description = r#"
+    Returns the number of listed tokens and their total cost up to each price level, cheapest first. With `sweep` set, also returns the cost of buying that many of the cheapest listings and the floor after
+"#)]
+pub async fn get_depth_profile(
+    #[data] pool: PgPool,
+    collection_slug: String,
+    query: rweb::Query<DepthRequest>,
+) -> Result<Json<Depth>, Rejection> {
+    let req: DepthRequest = query.into_inner();
+    println!("/get_depth/{}", collection_slug);
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    let depth = get_depth(
+        &mut conn,
+        &collection_slug,
+        req.trait_id.as_deref(),
+        req.sweep,
+        &Utc::now().naive_utc(),
+    )
+    .await
+    .map_err(internal_error)?;
+    let rate = get_eth_rate(&mut conn, req.currency, None)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(depth.in_currency(req.currency, rate).into())
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct DealsRequest {
+    /// Only tokens having this trait
+    pub trait_id: Option<String>,
+    /// Highest listing price, in `currency`
+    pub max_price: Option<f64>,
+    /// Lowest confidence score of the valuation, between 0 and 1
+    pub min_confidence: Option<f64>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[get("/deals/{collection_slug}")]
+#[openapi(tags("Collection"))]
+#[openapi(summary = "Get undervalued listings")]
+#[openapi(description = r#"
+    Returns the currently listed tokens of the collection ranked by listing price relative to their min_price, cheapest first
+"#)]
+pub async fn get_deals_profile(
+    #[data] pool: PgPool,
+    collection_slug: String,
+    query: rweb::Query<DealsRequest>,
+) -> Result<Json<Deals>, Rejection> {
+    let req: DealsRequest = query.into_inner();
+    println!("/get_deals/{}", collection_slug);
+
+    let rate = get_eth_rate(
+        &mut *pool.acquire().await.map_err(internal_error)?,
+        req.currency,
+        None,
+    )
+    .await
+    .map_err(internal_error)?;
+
+    let filter = DealFilter {
+        trait_id: req.trait_id,
+        max_price: req.max_price.map(|p| p / rate),
+        min_confidence: req.min_confidence,
+    };
+
+    get_deals(pool, &collection_slug, &filter)
+        .await
+        .map(|r| r.in_currency(req.currency, rate).into())
+        .map_err(internal_error)
+}
+
+#[get("/comps/{collection_slug}/{token_id}")]
+#[openapi(tags("Token"))]
+#[openapi(summary = "Get comparable sales")]
+#[openapi(description = r#"
+    Returns recent sales of tokens sharing the token's most valued trait, rarest trait or largest shared trait combination, adjusted for the collection's price movement since
+"#)]
+pub async fn get_comps_profile(
+    #[data] pool: PgPool,
+    token_id: i32,
+    collection_slug: String,
+    query: rweb::Query<CurrencyRequest>,
+) -> Result<Json<Comps>, Rejection> {
+    let req: CurrencyRequest = query.into_inner();
+    println!("/get_comps/{}/{}", collection_slug, token_id);
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    let collection = read_collection(&mut conn, &collection_slug)
+        .await
+        .map_err(internal_error)?;
+    let comps = get_token_comps(&mut conn, &collection, token_id, &Utc::now().naive_utc())
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            warp::reject::custom(ServiceError::BadRequest(format!(
+                "unknown token {}",
+                token_id
+            )))
+        })?;
+    let rates = EthRates::load(&mut conn, req.currency)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(comps.in_currency(&rates).into())
+}
+
+#[derive(serde::Deserialize, rweb::Schema)]
+pub struct SimilarRequest {
+    /// Number of tokens to return, defaults to 10
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[get("/similar/{collection_slug}/{token_id}")]
+#[openapi(tags("Token"))]
+#[openapi(summary = "Get most similar tokens")]
+#[openapi(description = r#"
+    Returns the tokens sharing the most traits with the token, rare traits weigh more than common ones. Ignored overlap trait types are left out
+"#)]
+pub async fn get_similar_tokens_profile(
+    #[data] pool: PgPool,
+    token_id: i32,
+    collection_slug: String,
+    query: rweb::Query<SimilarRequest>,
+) -> Result<Json<SimilarTokens>, Rejection> {
+    let req: SimilarRequest = query.into_inner();
+    println!("/get_similar/{}/{}", collection_slug, token_id);
+    let mut conn = pool.acquire().await.map_err(internal_error)?;
+
+    let similar = get_similar_tokens(
+        &mut conn,
+        &collection_slug,
+        token_id,
+        req.limit.unwrap_or(10),
+    )
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(|| {
+        warp::reject::custom(ServiceError::BadRequest(format!(
+            "unknown token {}",
+            token_id
+        )))
+    })?;
+    let rates = EthRates::load(&mut conn, req.currency)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(similar.in_currency(&rates).into())
+}
+
 #[derive(serde::Deserialize, rweb::Schema)]
 pub struct WalletProfileRequest {
     pub collection_slug: String,
     pub wallet: String,
     pub limit: i64,
     pub offset: i64,
+    #[serde(default)]
+    pub currency: Currency,
 }
 
 #[get("/wallet")]
 #[openapi(tags("Wallet"))]
 #[openapi(summary = "Get Wallet profile")]
 #[openapi(description = r#"
-Gets all pricings for tokens in collection in wallet and get total amounts
+Gets all pricings for tokens in collection in wallet and get total amounts. Tokens held are read from the synced asset owners, or from OpenSea if the collection is not fully synced
 "#)]
 pub async fn get_wallet_profile(
     #[data] pool: PgPool,
@@ -183,17 +611,26 @@ pub async fn get_wallet_profile(
         req.collection_slug, req.wallet, req.limit, req.offset
     );
 
+    let rate = get_eth_rate(
+        &mut *pool.acquire().await.map_err(internal_error)?,
+        req.currency,
+        None,
+    )
+    .await
+    .map_err(internal_error)?;
+
     _get_wallet_profile(pool, req.collection_slug, req.wallet, req.limit, req.offset)
         .await
-        .map(|r| r.into())
+        .map(|r| r.in_currency(req.currency, rate).into())
         .map_err(internal_error)
 }
 
 #[cached(
     size = 10,
+    time = 600,
     result = true,
     key = "String",
-    convert = r#"{ format!("{}{}{}{}", collection_slug, wallet, limit, offset) }"#
+    convert = r#"{ format!("{}:{}:{}:{}", collection_slug, wallet, limit, offset) }"#
 )]
 pub async fn _get_wallet_profile(
     pool: PgPool,
@@ -221,17 +658,26 @@ pub async fn get_wallet_profile_minimal(
         req.collection_slug, req.wallet, req.limit, req.offset
     );
 
+    let rate = get_eth_rate(
+        &mut *pool.acquire().await.map_err(internal_error)?,
+        req.currency,
+        None,
+    )
+    .await
+    .map_err(internal_error)?;
+
     _get_wallet_profile_minimal(pool, req.collection_slug, req.wallet, req.limit, req.offset)
         .await
-        .map(|r| r.into())
+        .map(|r| r.in_currency(req.currency, rate).into())
         .map_err(internal_error)
 }
 
 #[cached(
     size = 10,
+    time = 600,
     result = true,
     key = "String",
-    convert = r#"{ format!("{}{}{}{}", collection_slug, wallet, limit, offset) }"#
+    convert = r#"{ format!("{}:{}:{}:{}", collection_slug, wallet, limit, offset) }"#
 )]
 pub async fn _get_wallet_profile_minimal(
     pool: PgPool,
@@ -242,3 +688,42 @@ pub async fn _get_wallet_profile_minimal(
 ) -> Result<WalletProfile> {
     WalletProfile::make_minimal(pool, &collection_slug, &wallet, limit, offset).await
 }
+
+#[get("/portfolio/{address}")]
+#[openapi(tags("Wallet"))]
+#[openapi(summary = "Get portfolio of wallet")]
+#[openapi(description = r#"
+Gets the value of the wallet's tokens in every collection, the total over all collections and how concentrated the value is per collection
+"#)]
+pub async fn get_portfolio_profile(
+    #[data] pool: PgPool,
+    address: String,
+    query: rweb::Query<CurrencyRequest>,
+) -> Result<Json<PortfolioProfile>, Rejection> {
+    let req: CurrencyRequest = query.into_inner();
+    println!("/get_portfolio/{}", address);
+
+    let rate = get_eth_rate(
+        &mut *pool.acquire().await.map_err(internal_error)?,
+        req.currency,
+        None,
+    )
+    .await
+    .map_err(internal_error)?;
+
+    _get_portfolio_profile(pool, address)
+        .await
+        .map(|r| r.in_currency(req.currency, rate).into())
+        .map_err(internal_error)
+}
+
+#[cached(
+    size = 10,
+    time = 600,
+    result = true,
+    key = "String",
+    convert = r#"{ address.to_lowercase() }"#
+)]
+pub async fn _get_portfolio_profile(pool: PgPool, address: String) -> Result<PortfolioProfile> {
+    PortfolioProfile::make(pool, &address).await
+}
@@ -24,14 +24,27 @@ pub async fn start() {
             .and(handlers::status(pool.clone()).boxed())
             .or(handlers::user::get_profile(pool.clone()).boxed())
             .or(handlers::user::get_price_profile(pool.clone()).boxed())
+            .or(handlers::user::get_appraisal(pool.clone()).boxed())
+            .or(handlers::user::get_valuation_history(pool.clone()).boxed())
+            .or(handlers::user::get_deals_profile(pool.clone()).boxed())
+            .or(handlers::user::get_trait_ladder(pool.clone()).boxed())
+            .or(handlers::user::get_trait_history(pool.clone()).boxed())
+            .or(handlers::user::get_rarity_ranking(pool.clone()).boxed())
+            .or(handlers::user::get_depth_profile(pool.clone()).boxed())
+            .or(handlers::user::get_similar_tokens_profile(pool.clone()).boxed())
+            .or(handlers::user::get_comps_profile(pool.clone()).boxed())
             .or(handlers::user::get_collection_profile(pool.clone()).boxed())
             .or(handlers::user::get_wallet_profile(pool.clone()).boxed())
             .or(handlers::user::get_wallet_profile_minimal(pool.clone()).boxed())
+            .or(handlers::user::get_portfolio_profile(pool.clone()).boxed())
             .or(handlers::user::get_all_collections(pool.clone()).boxed())
             .or(handlers::admin::new_collection(pool.clone()).boxed())
             .or(handlers::admin::new_collection_minimal(pool.clone()).boxed())
             .or(handlers::admin::update_collection(pool.clone()).boxed())
             .or(handlers::admin::delete_collection(pool.clone()).boxed())
+            .or(handlers::admin::new_backtest(pool.clone()).boxed())
+            .or(handlers::admin::get_backtest(pool.clone()).boxed())
+            .or(handlers::admin::import_fx_rates(pool.clone()).boxed())
             .recover(handle_rejection)
             .with(cors)
     });
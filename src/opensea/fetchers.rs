@@ -50,6 +50,27 @@ pub async fn fetch_created(
     client.get_events(req).await
 }
 
+/// `event_type` is one of `offer_entered`, `bid_entered` or `bid_withdrawn`
+pub async fn fetch_bids(
+    client: &OpenseaAPIClient,
+    address: &str,
+    event_type: &str,
+    occurred_after: &NaiveDateTime,
+) -> Result<Vec<Event>> {
+    let req = EventsRequest::new()
+        .asset_contract_address(address)
+        .event_type(event_type)
+        .occurred_after(occurred_after)
+        .chunk_size(7)
+        .build();
+
+    let mut bids = client.get_events(req).await?;
+
+    bids.sort_by_key(|b| b.created_date);
+
+    Ok(bids)
+}
+
 pub async fn fetch_collection_sales(
     client: &OpenseaAPIClient,
     address: &str,
@@ -62,7 +83,7 @@ pub async fn fetch_collection_sales(
         .occurred_after(occurred_after)
         .build();
 
-    let mut sales = client.get_events(req).await.unwrap();
+    let mut sales = client.get_events(req).await?;
 
     sales.sort_by(|a, b| a.created_date.cmp(&b.created_date));
 
@@ -81,7 +102,7 @@ pub async fn fetch_collection_transfers(
         .occurred_after(occurred_after)
         .build();
 
-    let mut transfers = client.get_events(req).await.unwrap();
+    let mut transfers = client.get_events(req).await?;
 
     transfers.sort_by(|a, b| a.created_date.cmp(&b.created_date));
 
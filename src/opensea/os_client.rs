@@ -221,20 +221,12 @@ impl OpenseaAPIClient {
             })
             .buffer_unordered(6);
 
-        let mut results = vec![];
-
+        let mut chunks = vec![];
         while let Some(result) = stream.next().await {
-            match result {
-                Ok(mut resp) => {
-                    results.append(&mut resp);
-                }
-                Err(e) => {
-                    println!("Error: {:?}", e);
-                }
-            }
+            chunks.push(result);
         }
 
-        Ok(results)
+        merge_event_chunks(chunks)
     }
 
     pub async fn get_assets(&self, req: AssetsRequest) -> Result<Vec<Asset>> {
@@ -293,3 +285,41 @@ impl OpenseaAPIClient {
         b
     }
 }
+
+/// Events of all chunks, an error if any chunk failed so callers don't move their sync point
+/// past the missing events
+pub fn merge_event_chunks<T>(chunks: Vec<Result<Vec<T>>>) -> Result<Vec<T>> {
+    let nr_chunks = chunks.len();
+    let mut results = vec![];
+    let mut errors = vec![];
+    for chunk in chunks {
+        match chunk {
+            Ok(mut events) => results.append(&mut events),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!(
+            "{} of {} event chunks failed: {}",
+            errors.len(),
+            nr_chunks,
+            errors.join(", ")
+        ));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_event_chunks() {
+        assert_eq!(
+            merge_event_chunks(vec![Ok(vec![1, 2]), Ok(vec![3])]).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert!(merge_event_chunks(vec![Ok(vec![1]), Err(anyhow!("timeout"))]).is_err());
+    }
+}
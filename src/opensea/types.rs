@@ -103,21 +103,40 @@ pub struct AssetContract {
     image_url: Option<String>,
     default_to_fiat: bool,
     dev_buyer_fee_basis_points: u64,
-    dev_seller_fee_basis_points: u64,
+    pub dev_seller_fee_basis_points: u64,
     only_proxied_transfers: bool,
     opensea_buyer_fee_basis_points: u64,
-    opensea_seller_fee_basis_points: u64,
+    pub opensea_seller_fee_basis_points: u64,
     buyer_fee_basis_points: u64,
-    seller_fee_basis_points: u64,
+    pub seller_fee_basis_points: u64,
     payout_address: Option<String>,
 }
 
 impl Default for AssetContract {
     fn default() -> Self {
         Self {
+            address: String::default(),
+            asset_contract_type: AssetContractType::NonFungible,
             created_date: Utc::now().naive_utc(),
+            name: None,
+            nft_version: None,
+            opensea_version: None,
+            owner: None,
             schema_name: SchemaName::ERC721,
-            ..Default::default()
+            symbol: None,
+            total_supply: None,
+            description: None,
+            external_link: None,
+            image_url: None,
+            default_to_fiat: false,
+            dev_buyer_fee_basis_points: 0,
+            dev_seller_fee_basis_points: 0,
+            only_proxied_transfers: false,
+            opensea_buyer_fee_basis_points: 0,
+            opensea_seller_fee_basis_points: 0,
+            buyer_fee_basis_points: 0,
+            seller_fee_basis_points: 0,
+            payout_address: None,
         }
     }
 }
@@ -177,6 +196,11 @@ impl PaymentToken {
         let div = 10u64.pow(self.decimals - 2);
         (input_amount / div) as f64 / 100.0
     }
+
+    /// Converts a raw amount of this token to wei at its current `eth_price`
+    pub fn eth_wei_amount(&self, input_amount: f64) -> f64 {
+        input_amount / 10f64.powi(self.decimals as i32) * self.eth_price * 10f64.powi(18)
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -286,6 +310,40 @@ pub struct Event {
     pub payment_token: Option<PaymentToken>,
     pub to_account: Option<ToAccount>,
     pub winner_account: Option<ToAccount>,
+    pub seller: Option<ToAccount>,
+    pub from_account: Option<ToAccount>,
+    pub bid_amount: Option<String>,
+    /// Seconds an offer stays valid
+    pub duration: Option<String>,
+    /// What an offer without an asset is for, None for collection offers
+    pub criteria: Option<BidCriteria>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct BidCriteria {
+    #[serde(rename = "trait")]
+    pub trait_criteria: Option<TraitCriteria>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct TraitCriteria {
+    #[serde(rename = "type")]
+    pub trait_type: String,
+    /// A string or a number depending on the trait
+    pub value: serde_json::Value,
+}
+
+impl BidCriteria {
+    /// Id of the trait the offer is for, formatted like the stored `type:value` trait ids
+    pub fn trait_id(&self) -> Option<String> {
+        self.trait_criteria.as_ref().map(|t| {
+            let value = match &t.value {
+                serde_json::Value::String(v) => v.clone(),
+                v => v.to_string(),
+            };
+            format!("{}:{}", t.trait_type.to_lowercase(), value.to_lowercase())
+        })
+    }
 }
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct EventsResponse {
@@ -0,0 +1,78 @@
+use super::{
+    liquidty_profile::{LiquidityProfile, LIQUIDITY_WINDOW_DAYS},
+    price_profile::PriceProfile,
+    rarity_profile::RarityProfile,
+};
+use crate::analyzers::fx::Currency;
+use crate::analyzers::strategies::PricingContext;
+use crate::analyzers::window::Window;
+use crate::analyzers::TraitRarities;
+use crate::storage::Collection;
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::PgConnection;
+
+/// Profile of a hypothetical token that has exactly the given traits
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct AppraisalProfile {
+    pub collection_slug: String,
+    pub traits: Vec<String>,
+    pub price_profile: PriceProfile,
+    pub liquidity_profile: LiquidityProfile,
+    pub rarity_profile: RarityProfile,
+}
+
+impl AppraisalProfile {
+    /// `token_traits` must not be empty
+    pub async fn make(
+        conn: &mut PgConnection,
+        collection: Collection,
+        token_traits: Vec<TraitRarities>,
+        window: Option<Window>,
+        as_of: Option<NaiveDateTime>,
+    ) -> Result<Self> {
+        let ts = as_of.unwrap_or_else(|| Utc::now().naive_utc());
+
+        let ctx =
+            PricingContext::make(conn, &collection, None, Some(token_traits), window, &ts).await?;
+        let price_profile = PriceProfile::make(conn, &collection, &ctx).await?;
+        let token_traits = ctx.token_traits;
+        let rarest_trait = ctx.rarest_trait;
+        let most_valuable_trait_id = ctx.most_valuable_trait.map(|t| t.trait_id);
+
+        Ok(Self {
+            collection_slug: collection.slug.clone(),
+            traits: token_traits.iter().map(|t| t.trait_id.clone()).collect(),
+            liquidity_profile: LiquidityProfile::make(
+                conn,
+                &collection.slug,
+                &token_traits,
+                &rarest_trait,
+                price_profile.min_price,
+                price_profile.avg_price,
+                price_profile.max_price,
+                price_profile.top_bid,
+                &most_valuable_trait_id,
+                Window::resolve(window, &collection, LIQUIDITY_WINDOW_DAYS),
+                &ts,
+            )
+            .await?,
+            price_profile,
+            rarity_profile: RarityProfile::make_for_traits(
+                conn,
+                &collection,
+                &token_traits,
+                &rarest_trait,
+                &most_valuable_trait_id,
+            )
+            .await?,
+        })
+    }
+
+    /// Converts the price and liquidity profiles of the trait set to `currency`
+    pub fn in_currency(mut self, currency: Currency, rate: f64) -> Self {
+        self.price_profile = self.price_profile.in_currency(currency, rate);
+        self.liquidity_profile = self.liquidity_profile.in_currency(rate);
+        self
+    }
+}
@@ -1,11 +1,20 @@
+use crate::analyzers::fx::{Currency, EthRates};
+use crate::analyzers::window::{Window, Windowed};
+use crate::from_wei;
 use crate::storage::read::{
-    read_collection, read_listed_for_collection_at_ts, read_listing_update_type_count_after_ts,
+    read_collection, read_listed_for_collection_at_ts, read_listing_update_type_count_between_ts,
+    read_sales_for_collection_after_ts,
 };
 use anyhow::Result;
-use chrono::{Duration, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 use sqlx::PgConnection;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Default, Clone)]
+/// Window of the listing activity of collections without an analysis window
+pub static ACTIVITY_WINDOW_DAYS: i64 = 14;
+
+/// The daily, weekly and monthly figures are OpenSea's stats, at a past `as_of` they are
+/// computed from the stored sales before it instead
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
 pub struct CollectionProfile {
     pub banner_image_url: String,
     pub daily_volume: f64,
@@ -13,62 +22,174 @@ pub struct CollectionProfile {
     pub daily_avg_price: f64,
     pub weekly_avg_price: f64,
     pub monthly_avg_price: f64,
-    pub nr_owners: f64,
+    /// None at a past `as_of`, only the current owners are known
+    pub nr_owners: Option<f64>,
     pub avg_trait_rarity: f64,
     pub nr_listed_now: i64,
-    pub nr_new_listings_14d: i64,
-    pub nr_cancelled_listings_14d: i64,
-    pub nr_sales_14d: i64,
+    /// Listing activity over the window, the field names end in the window like `nr_sales_14d`
+    #[serde(flatten)]
+    pub activity: Windowed<CollectionActivity>,
+    pub currency: Currency,
+    /// (timestamp, price) of the stored sales behind the weekly and monthly averages
+    #[serde(skip)]
+    weekly_sales: Vec<(i32, f64)>,
+    #[serde(skip)]
+    monthly_sales: Vec<(i32, f64)>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct CollectionActivity {
+    pub nr_new_listings: i64,
+    pub nr_cancelled_listings: i64,
+    pub nr_sales: i64,
 }
 
 impl CollectionProfile {
-    pub async fn make(conn: &mut PgConnection, collection_slug: &str) -> Result<Self> {
+    /// `window` overrides the window of the collection's settings
+    pub async fn make(
+        conn: &mut PgConnection,
+        collection_slug: &str,
+        window: Option<Window>,
+        as_of: Option<NaiveDateTime>,
+    ) -> Result<Self> {
+        let ts = &as_of.unwrap_or_else(|| Utc::now().naive_utc());
         log::info!("Getting collection");
 
         let collection = read_collection(conn, collection_slug).await?;
 
         log::info!("Getting nr_listed_now");
-        let nr_listed_now =
-            read_listed_for_collection_at_ts(conn, collection_slug, &Utc::now().naive_utc())
-                .await?
-                .len();
+        let nr_listed_now = read_listed_for_collection_at_ts(conn, collection_slug, ts)
+            .await?
+            .len();
 
-        let ts_14d_ago = (Utc::now() - Duration::days(14)).naive_utc();
+        let window = Window::resolve(window, &collection, ACTIVITY_WINDOW_DAYS);
+        let start = window.start(ts);
 
-        Ok(Self {
+        let monthly_sales =
+            read_sales_for_collection_after_ts(conn, collection_slug, &(*ts - Duration::days(30)))
+                .await?
+                .into_iter()
+                .filter(|s| (s.timestamp as i64) <= ts.timestamp())
+                .map(|s| (s.timestamp, from_wei(s.price)))
+                .collect::<Vec<_>>();
+        let week_start = (*ts - Duration::days(7)).timestamp();
+        let weekly_sales = monthly_sales
+            .iter()
+            .filter(|(t, _)| *t as i64 > week_start)
+            .copied()
+            .collect();
+
+        let mut profile = Self {
             banner_image_url: collection.banner_image_url.clone(),
             daily_volume: collection.daily_volume,
             daily_sales: collection.daily_sales,
             daily_avg_price: collection.daily_avg_price,
             weekly_avg_price: collection.weekly_avg_price,
             monthly_avg_price: collection.monthly_avg_price,
-            nr_owners: collection.nr_owners,
+            nr_owners: Some(collection.nr_owners),
             avg_trait_rarity: collection.avg_trait_rarity,
             nr_listed_now: nr_listed_now as i64,
-            nr_new_listings_14d: read_listing_update_type_count_after_ts(
-                conn,
-                collection_slug,
-                "created",
-                &ts_14d_ago,
-            )
-            .await?
-            .unwrap_or_default(),
-            nr_cancelled_listings_14d: read_listing_update_type_count_after_ts(
-                conn,
-                collection_slug,
-                "cancelled",
-                &ts_14d_ago,
-            )
-            .await?
-            .unwrap_or_default(),
-            nr_sales_14d: read_listing_update_type_count_after_ts(
-                conn,
-                collection_slug,
-                "successful",
-                &ts_14d_ago,
-            )
-            .await?
-            .unwrap_or_default(),
-        })
+            activity: Windowed::new(
+                window,
+                CollectionActivity {
+                    nr_new_listings: read_listing_update_type_count_between_ts(
+                        conn,
+                        collection_slug,
+                        "created",
+                        &start,
+                        ts,
+                    )
+                    .await?
+                    .unwrap_or_default(),
+                    nr_cancelled_listings: read_listing_update_type_count_between_ts(
+                        conn,
+                        collection_slug,
+                        "cancelled",
+                        &start,
+                        ts,
+                    )
+                    .await?
+                    .unwrap_or_default(),
+                    nr_sales: read_listing_update_type_count_between_ts(
+                        conn,
+                        collection_slug,
+                        "successful",
+                        &start,
+                        ts,
+                    )
+                    .await?
+                    .unwrap_or_default(),
+                },
+            ),
+            currency: Currency::Eth,
+            weekly_sales,
+            monthly_sales,
+        };
+
+        // OpenSea only has today's stats
+        if as_of.is_some() {
+            let day_start = (*ts - Duration::days(1)).timestamp();
+            let daily_prices = profile
+                .weekly_sales
+                .iter()
+                .filter(|(t, _)| *t as i64 > day_start)
+                .map(|(_, p)| *p)
+                .collect::<Vec<_>>();
+            profile.daily_volume = daily_prices.iter().fold(0f64, |v, p| v + p);
+            profile.daily_sales = daily_prices.len() as f64;
+            profile.daily_avg_price = get_avg_price(&daily_prices);
+            profile.weekly_avg_price = get_avg_price(
+                &profile
+                    .weekly_sales
+                    .iter()
+                    .map(|(_, p)| *p)
+                    .collect::<Vec<_>>(),
+            );
+            profile.monthly_avg_price = get_avg_price(
+                &profile
+                    .monthly_sales
+                    .iter()
+                    .map(|(_, p)| *p)
+                    .collect::<Vec<_>>(),
+            );
+            profile.nr_owners = None;
+        }
+
+        Ok(profile)
+    }
+
+    /// Converts the daily figures at the rate at `ts`, the latest if None, and the weekly and
+    /// monthly averages at the rates of their sales
+    pub fn in_currency(mut self, rates: &EthRates, ts: Option<&NaiveDateTime>) -> Self {
+        let rate = rates.rate(ts);
+        self.daily_volume *= rate;
+        self.daily_avg_price *= rate;
+        self.weekly_avg_price *= get_sales_rate(&self.weekly_sales, rates).unwrap_or(rate);
+        self.monthly_avg_price *= get_sales_rate(&self.monthly_sales, rates).unwrap_or(rate);
+        self.currency = rates.currency;
+        self
+    }
+}
+
+/// 0 without sales, like OpenSea's stats
+fn get_avg_price(prices: &[f64]) -> f64 {
+    if prices.is_empty() {
+        return 0f64;
     }
+    prices.iter().sum::<f64>() / prices.len() as f64
+}
+
+/// Average rate of the sales weighted by price, their average price converted at it equals the
+/// average of every sale converted at the rate of its time. None without sales
+fn get_sales_rate(sales: &[(i32, f64)], rates: &EthRates) -> Option<f64> {
+    let volume = sales.iter().map(|(_, p)| p).sum::<f64>();
+    if volume <= 0f64 {
+        return None;
+    }
+
+    let converted = sales
+        .iter()
+        .map(|(t, p)| p * rates.rate(Some(&NaiveDateTime::from_timestamp(*t as i64, 0))))
+        .sum::<f64>();
+    Some(converted / volume)
 }
@@ -1,29 +1,57 @@
+use crate::analyzers::bids::get_trait_top_bids;
 use crate::analyzers::liquidty::*;
 use crate::analyzers::listings::*;
-use crate::storage::read::read_sales_for_collection_above_price_after_ts;
+use crate::analyzers::time_to_sell::{get_time_to_sell, TimeToSell};
+use crate::analyzers::window::{Window, Windowed};
+use crate::analyzers::TraitRarities;
+use crate::storage::read::read_sales_for_collection_above_price_between_ts;
 use crate::storage::read::read_trait;
 use anyhow::Result;
-use chrono::{Duration, Utc};
+use chrono::NaiveDateTime;
 use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// Window of the sale counts of collections without an analysis window
+pub static LIQUIDITY_WINDOW_DAYS: i64 = 60;
+
 #[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
 pub struct LiquidityProfile {
     pub rarest_trait_nr_listed: (usize, usize),
     pub mvt_nr_listed: (usize, usize),
-    pub rarest_trait_sale_count_60d: usize,
-    pub mvt_sale_count_60d: usize,
-    pub lowest_trait_sales_60d: usize,
-    pub avg_sale_count_60d: f64,
-    pub nr_sales_above_max_price_60d: usize,
+    /// Sale counts over the window, the field names end in the window like `mvt_sale_count_60d`
+    #[serde(flatten)]
+    pub sales: Windowed<LiquiditySales>,
+    /// Highest open bid for the token, what it can be sold for instantly
+    pub top_bid: Option<f64>,
+    /// Highest open bid per trait of the token, traits without bids are left out
+    pub trait_top_bids: HashMap<String, f64>,
+    /// Expected days to sell at `min_price`, `avg_price` and `max_price` of the price profile
+    pub time_to_sell: Vec<TimeToSell>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct LiquiditySales {
+    pub rarest_trait_sale_count: usize,
+    pub mvt_sale_count: usize,
+    pub lowest_trait_sales: usize,
+    pub avg_sale_count: f64,
+    pub nr_sales_above_max_price: usize,
 }
 
 impl LiquidityProfile {
+    #[allow(clippy::too_many_arguments)]
     pub async fn make(
         conn: &mut PgConnection,
         collection_slug: &str,
-        token_id: i32,
+        token_traits: &[TraitRarities],
         rarest_trait: &str,
+        min_price: f64,
+        avg_price: f64,
         max_price: f64,
+        top_bid: Option<f64>,
         most_valuable_trait: &Option<String>,
+        window: Window,
+        ts: &NaiveDateTime,
     ) -> Result<Self> {
         let rarest_trait_count = read_trait(conn, collection_slug, rarest_trait)
             .await?
@@ -31,47 +59,81 @@ impl LiquidityProfile {
 
         log::info!("Getting rarest_trait_nr_listed");
         let rarest_trait_nr_listed =
-            get_trait_nr_listed(conn, collection_slug, rarest_trait).await?;
+            get_trait_nr_listed(conn, collection_slug, rarest_trait, ts).await?;
 
-        let (mvt_trait_count, mvt_nr_listed, mvt_sale_count_60d) = match most_valuable_trait.clone()
-        {
+        let days_back = window.days as usize;
+        let (mvt_trait_count, mvt_nr_listed, mvt_sale_count) = match most_valuable_trait.clone() {
             Some(t) => (
                 read_trait(conn, collection_slug, &t).await?.trait_count,
-                get_trait_nr_listed(conn, collection_slug, &t).await?,
-                get_sale_count_trait(conn, collection_slug, &t, 60).await?,
+                get_trait_nr_listed(conn, collection_slug, &t, ts).await?,
+                get_sale_count_trait(conn, collection_slug, &t, days_back, ts).await?,
             ),
             None => (0, 0, 0),
         };
 
-        log::info!("Getting avg_sale_count_30d");
-        let avg_sale_count_60d = get_avg_sale_count(conn, collection_slug, token_id, 60).await?;
+        log::info!("Getting avg_sale_count");
+        let avg_sale_count =
+            get_avg_sale_count(conn, collection_slug, token_traits, days_back, ts).await?;
 
         log::info!("Getting lowest_trait_sales");
-        let lowest_trait_sales_60d =
-            get_lowest_sale_count(conn, collection_slug, token_id, 60).await?;
+        let lowest_trait_sales =
+            get_lowest_sale_count(conn, collection_slug, token_traits, days_back, ts).await?;
 
         log::info!("Getting rarest_trait_sale_count");
-        let rarest_trait_sale_count_60d =
-            get_sale_count_trait(conn, collection_slug, rarest_trait, 60).await?;
+        let rarest_trait_sale_count =
+            get_sale_count_trait(conn, collection_slug, rarest_trait, days_back, ts).await?;
 
-        log::info!("Getting nr_sales_above_max_price_60d");
-        let nr_sales_above_max_price_60d = read_sales_for_collection_above_price_after_ts(
+        log::info!("Getting nr_sales_above_max_price");
+        let nr_sales_above_max_price = read_sales_for_collection_above_price_between_ts(
             conn,
             collection_slug,
             max_price,
-            &(Utc::now() - Duration::days(60)).naive_utc(),
+            &window.start(ts),
+            ts,
         )
         .await?
         .len();
 
+        log::info!("Getting time_to_sell");
+        let trait_ids = token_traits
+            .iter()
+            .map(|t| t.trait_id.clone())
+            .collect::<Vec<_>>();
+        let time_to_sell = get_time_to_sell(
+            conn,
+            collection_slug,
+            &trait_ids,
+            &[min_price, avg_price, max_price],
+            avg_price,
+            ts,
+        )
+        .await?;
+
         Ok(Self {
             rarest_trait_nr_listed: (rarest_trait_nr_listed, rarest_trait_count as usize),
             mvt_nr_listed: (mvt_nr_listed, mvt_trait_count as usize),
-            lowest_trait_sales_60d: lowest_trait_sales_60d.1,
-            rarest_trait_sale_count_60d,
-            avg_sale_count_60d,
-            mvt_sale_count_60d,
-            nr_sales_above_max_price_60d,
+            sales: Windowed::new(
+                window,
+                LiquiditySales {
+                    rarest_trait_sale_count,
+                    mvt_sale_count,
+                    lowest_trait_sales: lowest_trait_sales.1,
+                    avg_sale_count,
+                    nr_sales_above_max_price,
+                },
+            ),
+            top_bid,
+            trait_top_bids: get_trait_top_bids(conn, collection_slug, token_traits, ts).await?,
+            time_to_sell,
         })
     }
+
+    /// Multiplies the top bids and the prices of the time to sell estimates by `rate`, the
+    /// profile has no currency field of its own
+    pub fn in_currency(mut self, rate: f64) -> Self {
+        self.top_bid = self.top_bid.map(|p| p * rate);
+        self.trait_top_bids.values_mut().for_each(|p| *p *= rate);
+        self.time_to_sell.iter_mut().for_each(|t| t.price *= rate);
+        self
+    }
 }
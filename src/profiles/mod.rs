@@ -1,6 +1,10 @@
+pub mod appraisal_profile;
 pub mod collection_profile;
 pub mod liquidty_profile;
+pub mod portfolio_profile;
 pub mod price_profile;
 pub mod rarity_profile;
 pub mod token_profile;
+pub mod trait_profile;
+pub mod valuation_profile;
 pub mod wallet_profile;
@@ -0,0 +1,176 @@
+use crate::analyzers::fx::Currency;
+use crate::analyzers::wallet::{get_value_for_wallet, get_wallet_token_ids, Ownership};
+use crate::storage::read::{read_all_collections, read_collection};
+use anyhow::Result;
+use futures::StreamExt;
+use sqlx::PgPool;
+
+/// Tokens of one collection held by the wallet
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct PortfolioCollection {
+    pub collection_slug: String,
+    pub collection_name: String,
+    /// Tokens priced, those that failed are listed in the portfolio's `errors`
+    pub nr_tokens: usize,
+    pub value_min: f64,
+    pub value_avg: f64,
+    pub value_max: f64,
+    /// Share of the portfolio's total `value_avg`
+    pub concentration: f64,
+    pub ownership: Ownership,
+}
+
+/// Collection, or some of its tokens, the wallet holds but could not be valued
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct PortfolioError {
+    pub collection_slug: String,
+    pub error: String,
+    /// Tokens that failed to price, empty if the whole collection failed
+    pub token_ids: Vec<i32>,
+}
+
+/// Value of the wallet's tokens across all collections
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct PortfolioProfile {
+    pub address: String,
+    pub nr_tokens: usize,
+    pub total_value_min: f64,
+    pub total_value_avg: f64,
+    pub total_value_max: f64,
+    /// Sum of the squared concentrations, 1 if all value is in a single collection
+    pub concentration_index: f64,
+    /// Collections the wallet holds tokens of, most valuable first
+    pub collections: Vec<PortfolioCollection>,
+    /// Collections and tokens left out of the totals because they could not be valued
+    pub errors: Vec<PortfolioError>,
+    pub currency: Currency,
+}
+
+impl PortfolioProfile {
+    pub async fn make(pool: PgPool, address: &str) -> Result<Self> {
+        let mut conn = pool.acquire().await?;
+        let slugs = read_all_collections(&mut conn)
+            .await?
+            .into_iter()
+            .map(|c| c.slug)
+            .collect::<Vec<_>>();
+        drop(conn);
+
+        // most collections fall back to OpenSea for the owners, so they are valued concurrently
+        let mut stream = futures::stream::iter(slugs)
+            .map(|slug| {
+                let pool = pool.clone();
+                async move {
+                    let collection = make_collection(pool, &slug, address).await;
+                    (slug, collection)
+                }
+            })
+            .buffer_unordered(6);
+
+        let mut collections = vec![];
+        let mut errors = vec![];
+        while let Some((slug, result)) = stream.next().await {
+            match result {
+                Ok(Some((c, failed_ids))) => {
+                    if !failed_ids.is_empty() {
+                        errors.push(PortfolioError {
+                            collection_slug: slug,
+                            error: format!("{} tokens could not be priced", failed_ids.len()),
+                            token_ids: failed_ids,
+                        });
+                    }
+                    if c.nr_tokens > 0 {
+                        collections.push(c);
+                    }
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    log::info!("Error valuing {} of {}: {}", slug, address, e);
+                    errors.push(PortfolioError {
+                        collection_slug: slug,
+                        error: e.to_string(),
+                        token_ids: vec![],
+                    });
+                }
+            }
+        }
+        errors.sort_by(|a, b| a.collection_slug.cmp(&b.collection_slug));
+
+        let mut total_value_min = 0f64;
+        let mut total_value_avg = 0f64;
+        let mut total_value_max = 0f64;
+        for c in &collections {
+            total_value_min += c.value_min;
+            total_value_avg += c.value_avg;
+            total_value_max += c.value_max;
+        }
+
+        let mut concentration_index = 0f64;
+        if total_value_avg > 0f64 {
+            for c in collections.iter_mut() {
+                c.concentration = c.value_avg / total_value_avg;
+                concentration_index += c.concentration.powi(2);
+            }
+        }
+        collections.sort_by(|a, b| b.value_avg.partial_cmp(&a.value_avg).unwrap());
+
+        Ok(Self {
+            address: address.to_string(),
+            nr_tokens: collections.iter().map(|c| c.nr_tokens).sum(),
+            total_value_min,
+            total_value_avg,
+            total_value_max,
+            concentration_index,
+            collections,
+            errors,
+            currency: Currency::Eth,
+        })
+    }
+
+    /// Converts the totals and the value of every collection to `currency`, concentrations are
+    /// shares of the total and stay the same
+    pub fn in_currency(mut self, currency: Currency, rate: f64) -> Self {
+        self.total_value_min *= rate;
+        self.total_value_avg *= rate;
+        self.total_value_max *= rate;
+        for c in self.collections.iter_mut() {
+            c.value_min *= rate;
+            c.value_avg *= rate;
+            c.value_max *= rate;
+        }
+        self.currency = currency;
+        self
+    }
+}
+
+/// The priced tokens of the collection and the ids of those that failed to price, None if the
+/// wallet holds no tokens of the collection
+async fn make_collection(
+    pool: PgPool,
+    collection_slug: &str,
+    address: &str,
+) -> Result<Option<(PortfolioCollection, Vec<i32>)>> {
+    let mut conn = pool.acquire().await?;
+    let collection = read_collection(&mut conn, collection_slug).await?;
+    let (ids, ownership) = get_wallet_token_ids(&mut conn, &collection, address).await?;
+    drop(conn);
+    if ids.is_empty() {
+        return Ok(None);
+    }
+
+    let limit = ids.len() as i64;
+    let (value_max, value_min, value_avg, _, profiles, _, failed_ids) =
+        get_value_for_wallet(pool, &collection, ids, limit, 0).await?;
+
+    let collection = PortfolioCollection {
+        collection_slug: collection.slug,
+        collection_name: collection.name,
+        nr_tokens: profiles.len(),
+        value_min,
+        value_avg,
+        value_max,
+        concentration: 0f64,
+        ownership,
+    };
+    Ok(Some((collection, failed_ids)))
+}
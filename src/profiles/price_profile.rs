@@ -1,124 +1,152 @@
-use crate::analyzers::prices::*;
-use crate::analyzers::sales::*;
-use crate::analyzers::*;
+use crate::analyzers::bids::{get_price_range_above_bid, get_top_bid};
+use crate::analyzers::confidence::*;
+use crate::analyzers::fees::FeeBreakdown;
+use crate::analyzers::fx::Currency;
+use crate::analyzers::hedonic::get_trait_premiums;
+use crate::analyzers::strategies::*;
 use crate::custom::read_custom_price;
+use crate::storage::Collection;
 use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
 use sqlx::PgConnection;
+use std::collections::HashMap;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Default)]
 pub struct PriceProfile {
     pub collection_floor: f64,
-    pub last_sale: Option<f64>,
-    pub most_rare_trait_floor: Option<f64>,
-    pub most_valued_trait_floor: Option<f64>,
-    pub floor_staircase_price: Option<f64>,
-    pub rarity_weighted_floor: Option<f64>,
-    pub avg_last_three_mvt_sales: Option<f64>,
-    pub last_sale_relative_collection_avg: Option<f64>,
-    pub last_sale_relative_mvt_avg: Option<f64>,
+    pub strategies: HashMap<String, Option<f64>>,
+    /// Price multiplier of each of the token's traits according to the hedonic model
+    pub trait_premiums: HashMap<String, f64>,
     pub custom_price: Option<f64>,
     pub max_price: f64,
     pub min_price: f64,
     pub avg_price: f64,
+    /// What the seller receives at `min_price`, `avg_price` and `max_price` after fees
+    pub net_min_price: f64,
+    pub net_avg_price: f64,
+    pub net_max_price: f64,
+    pub fees: FeeBreakdown,
+    /// Highest open bid for the token, its traits or the collection, `min_price` is never below it
+    pub top_bid: Option<f64>,
+    /// How much evidence backs `avg_price`, with an 80% interval around it
+    pub confidence: PriceConfidence,
+    pub currency: Currency,
 }
 
 impl PriceProfile {
+    /// Prices the token or trait set of `ctx` with the strategies enabled for the collection
     pub async fn make(
         conn: &mut PgConnection,
-        collection_slug: &str,
-        token_id: i32,
-        token_traits: Vec<TraitRarities>,
-        rarest_trait: &str,
-        most_valuable_trait: &Option<TraitFloor>,
-        cutoff: f64,
+        collection: &Collection,
+        ctx: &PricingContext,
     ) -> Result<Self> {
-        log::info!("Getting collection_floor");
-        let collection_floor = get_collection_floor(conn, collection_slug).await?;
-
-        log::info!("Getting most_rare_trait_floor");
-        let most_rare_trait_floor =
-            get_trait_floor(conn, collection_slug, <&str>::clone(&rarest_trait))
-                .await?
-                .map(|t| t.floor_price);
-
-        log::info!("Getting last_sale");
-        let last_sale = get_asset_sales(conn, collection_slug, token_id)
-            .await?
-            .last()
-            .cloned();
-
-        log::info!("Getting mvt data");
-        let (most_valued_trait_floor, avg_last_three_mvt_sales, last_sale_relative_mvt_avg) =
-            match most_valuable_trait {
-                Some(t) => (
-                    Some(t.floor_price),
-                    get_average_trait_sales_nr(conn, collection_slug, &t.trait_id, Some(3)).await?,
-                    get_last_sale_relative_to_trait_avg(
-                        conn,
-                        collection_slug,
-                        &t.trait_id,
-                        &last_sale,
-                    )
-                    .await?,
-                ),
-                None => (None, None, None),
-            };
-
-        log::info!("Getting rarity_weighted_floor");
-        let rarity_weighted_floor =
-            get_rarity_weighted_floor(conn, collection_slug, token_traits.clone(), cutoff).await?;
-
-        log::info!("Getting flattening_staircase_price");
-        let floor_staircase_price = get_flattening_staircase_price(
+        let strategies = run_strategies(
             conn,
-            collection_slug,
-            collection_floor,
-            token_traits,
-            cutoff,
+            &enabled_strategies(&collection.pricing_strategies),
+            ctx,
         )
         .await?;
 
-        log::info!("Getting last_sale_relative_collection_avg");
-        let last_sale_relative_collection_avg =
-            get_last_sale_relative_to_collection_avg(conn, collection_slug, &last_sale).await?;
+        log::info!("Getting top_bid");
+        let top_bid = get_top_bid(
+            conn,
+            &ctx.collection_slug,
+            ctx.token_id,
+            &ctx.token_traits,
+            &ctx.ts,
+        )
+        .await?;
 
-        let mut prices = vec![
-            collection_floor,
-            last_sale.clone().unwrap_or_default().price,
-            most_rare_trait_floor.unwrap_or(0f64),
-            most_valued_trait_floor.unwrap_or(0f64),
-            rarity_weighted_floor.unwrap_or(0f64),
-            floor_staircase_price.unwrap_or(0f64),
-            avg_last_three_mvt_sales.unwrap_or(0f64),
-            last_sale_relative_collection_avg.unwrap_or(0f64),
-            last_sale_relative_mvt_avg.unwrap_or(0f64),
-        ]
-        .into_iter()
-        .filter(|p| p > &0f64)
-        .collect::<Vec<_>>();
+        let (min_price, max_price, avg_price) =
+            get_price_range_above_bid(get_price_range(&strategies, ctx.collection_floor), top_bid);
 
-        prices.sort_by(|a, b| b.partial_cmp(a).unwrap());
-        let max_price = prices[0];
+        log::info!("Getting confidence");
+        let confidence = get_price_confidence(conn, ctx, &strategies, avg_price).await?;
 
-        prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let min_price = f64::max(prices[0], collection_floor);
+        let fees = FeeBreakdown::make(collection, avg_price);
 
-        let avg_price = (max_price + min_price) / 2f64;
+        log::info!("Getting trait_premiums");
+        let trait_premiums =
+            get_trait_premiums(conn, &ctx.collection_slug, &ctx.token_traits, &ctx.ts).await?;
 
         Ok(Self {
-            collection_floor,
-            last_sale: last_sale.map(|l| l.price),
-            most_rare_trait_floor,
-            most_valued_trait_floor,
-            floor_staircase_price,
-            rarity_weighted_floor,
-            avg_last_three_mvt_sales,
-            last_sale_relative_collection_avg,
-            last_sale_relative_mvt_avg,
-            custom_price: read_custom_price(collection_slug, token_id)?,
+            collection_floor: ctx.collection_floor,
+            strategies,
+            trait_premiums,
+            custom_price: match ctx.token_id {
+                Some(id) => read_custom_price(&ctx.collection_slug, id)?,
+                None => None,
+            },
             max_price,
             min_price,
             avg_price,
+            net_min_price: fees.net_price(min_price),
+            net_avg_price: fees.net_price(avg_price),
+            net_max_price: fees.net_price(max_price),
+            fees,
+            top_bid,
+            confidence,
+            currency: Currency::Eth,
         })
     }
+
+    /// Looks up the traits of the token, None if the token has no stored traits
+    pub async fn make_for_token(
+        conn: &mut PgConnection,
+        collection: &Collection,
+        token_id: i32,
+        as_of: Option<NaiveDateTime>,
+    ) -> Result<Option<Self>> {
+        let ts = as_of.unwrap_or_else(|| Utc::now().naive_utc());
+        let ctx = PricingContext::make(conn, collection, Some(token_id), None, None, &ts).await?;
+        if ctx.token_traits.is_empty() {
+            return Ok(None);
+        }
+
+        Self::make(conn, collection, &ctx).await.map(Some)
+    }
+
+    /// Profile of a token whose price is set in the custom prices file
+    pub fn from_custom_price(price: f64, collection: &Collection) -> Self {
+        let fees = FeeBreakdown::make(collection, price);
+        Self {
+            max_price: price,
+            min_price: price,
+            avg_price: price,
+            net_min_price: fees.net_price(price),
+            net_avg_price: fees.net_price(price),
+            net_max_price: fees.net_price(price),
+            fees,
+            confidence: PriceConfidence {
+                interval: (price, price),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Converts the strategy prices, the price range with and without fees, the top bid and the
+    /// confidence interval at `rate`, the price of one ETH in `currency`
+    pub fn in_currency(mut self, currency: Currency, rate: f64) -> Self {
+        self.collection_floor *= rate;
+        self.strategies
+            .values_mut()
+            .flatten()
+            .for_each(|p| *p *= rate);
+        self.custom_price = self.custom_price.map(|p| p * rate);
+        self.max_price *= rate;
+        self.min_price *= rate;
+        self.avg_price *= rate;
+        self.net_max_price *= rate;
+        self.net_min_price *= rate;
+        self.net_avg_price *= rate;
+        self.fees = self.fees.in_currency(rate);
+        self.top_bid = self.top_bid.map(|p| p * rate);
+        self.confidence.interval = (
+            self.confidence.interval.0 * rate,
+            self.confidence.interval.1 * rate,
+        );
+        self.currency = currency;
+        self
+    }
 }
@@ -1,5 +1,12 @@
-use crate::storage::read::read_asset;
+use crate::analyzers::rarity_scores::get_rarity_model;
+use crate::analyzers::TraitRarities;
+use crate::storage::preprocess::get_combination_overlap;
+use crate::storage::read::{
+    read_asset, read_asset_overlaps, read_nr_rarer_assets, read_rarity_ranking, read_rarity_score,
+};
+use crate::storage::{AssetOverlap, Collection, RarityScore};
 use anyhow::Result;
+use itertools::Itertools;
 use sqlx::PgConnection;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
@@ -7,13 +14,58 @@ pub struct RarityProfile {
     pub rarest_trait: String,
     pub most_valued_trait: Option<String>,
     pub unique_traits: i32,
-    pub traits_3_combination_overlap: i32,
-    pub traits_3_combination_overlap_ids: Vec<i32>,
-    pub traits_4_combination_overlap: i32,
-    pub traits_4_combination_overlap_ids: Vec<i32>,
-    pub traits_5_combination_overlap: i32,
-    pub traits_5_combination_overlap_ids: Vec<i32>,
+    /// One entry per combination size configured for the collection, smallest first
+    pub combination_overlaps: Vec<CombinationOverlap>,
+    /// None until the scores of the collection are stored
+    pub scores: Option<RarityScores>,
 }
+
+/// Tokens sharing all traits of at least one `combination_size` combination of the traits,
+/// the token itself included
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct CombinationOverlap {
+    pub combination_size: i32,
+    pub nr_overlapping: i32,
+    pub overlapping_ids: Vec<i32>,
+}
+
+impl std::convert::From<AssetOverlap> for CombinationOverlap {
+    fn from(o: AssetOverlap) -> Self {
+        Self {
+            combination_size: o.combination_size,
+            nr_overlapping: o.nr_overlapping,
+            overlapping_ids: o.overlapping_ids,
+        }
+    }
+}
+
+/// Rarity under every model, rank 1 is the rarest asset of the collection
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct RarityScores {
+    /// Product of the trait frequencies, lower is rarer
+    pub statistical_rarity: f64,
+    /// Sum of the inverse trait frequencies
+    pub rarity_score: f64,
+    /// Sum of the information content of the traits in bits
+    pub information_content: f64,
+    pub statistical_rarity_rank: i32,
+    pub rarity_score_rank: i32,
+    pub information_content_rank: i32,
+}
+
+impl std::convert::From<RarityScore> for RarityScores {
+    fn from(s: RarityScore) -> Self {
+        Self {
+            statistical_rarity: s.statistical_rarity,
+            rarity_score: s.rarity_score,
+            information_content: s.information_content,
+            statistical_rarity_rank: s.statistical_rarity_rank,
+            rarity_score_rank: s.rarity_score_rank,
+            information_content_rank: s.information_content_rank,
+        }
+    }
+}
+
 impl RarityProfile {
     pub async fn make(
         conn: &mut PgConnection,
@@ -29,12 +81,130 @@ impl RarityProfile {
             rarest_trait: rarest_trait.into(),
             most_valued_trait: most_valued_trait.clone(),
             unique_traits: asset.unique_traits,
-            traits_3_combination_overlap: asset.traits_3_combination_overlap,
-            traits_4_combination_overlap: asset.traits_4_combination_overlap,
-            traits_5_combination_overlap: asset.traits_5_combination_overlap,
-            traits_3_combination_overlap_ids: asset.traits_3_combination_overlap_ids,
-            traits_4_combination_overlap_ids: asset.traits_4_combination_overlap_ids,
-            traits_5_combination_overlap_ids: asset.traits_5_combination_overlap_ids,
+            combination_overlaps: read_asset_overlaps(conn, collection_slug, token_id)
+                .await?
+                .into_iter()
+                .map(|o| o.into())
+                .collect(),
+            scores: read_rarity_score(conn, collection_slug, token_id)
+                .await?
+                .map(|s| s.into()),
+        })
+    }
+
+    /// Profile of a hypothetical token, the overlaps are computed instead of read from the asset
+    pub async fn make_for_traits(
+        conn: &mut PgConnection,
+        collection: &Collection,
+        token_traits: &[TraitRarities],
+        rarest_trait: &str,
+        most_valued_trait: &Option<String>,
+    ) -> Result<Self> {
+        let unique_traits = token_traits
+            .iter()
+            .filter(|t| (t.rarity * collection.total_supply as f64).round() as i32 == 1)
+            .count();
+
+        let traits = token_traits
+            .iter()
+            .map(|t| t.trait_id.clone())
+            .filter(|t| {
+                !collection
+                    .ignored_trait_types_overlap
+                    .iter()
+                    .any(|i| t.starts_with(&format!("{}:", i)))
+            })
+            .collect::<Vec<_>>();
+
+        log::info!("Getting combination overlaps");
+        let mut combination_overlaps = vec![];
+        for size in collection.overlap_sizes.iter().sorted() {
+            let overlapping_ids =
+                get_combination_overlap(conn, &collection.slug, &traits, *size as usize)
+                    .await?
+                    .into_iter()
+                    .sorted()
+                    .collect::<Vec<_>>();
+            combination_overlaps.push(CombinationOverlap {
+                combination_size: *size,
+                nr_overlapping: overlapping_ids.len() as i32,
+                overlapping_ids,
+            });
+        }
+
+        log::info!("Scoring rarity");
+        let trait_ids = token_traits
+            .iter()
+            .map(|t| t.trait_id.clone())
+            .collect::<Vec<_>>();
+        let (statistical_rarity, rarity_score, information_content) =
+            get_rarity_model(conn, collection).await?.score(&trait_ids);
+        let (rarer_statistical, rarer_score, rarer_information) = read_nr_rarer_assets(
+            conn,
+            &collection.slug,
+            statistical_rarity,
+            rarity_score,
+            information_content,
+        )
+        .await?;
+
+        Ok(Self {
+            rarest_trait: rarest_trait.into(),
+            most_valued_trait: most_valued_trait.clone(),
+            unique_traits: unique_traits as i32,
+            combination_overlaps,
+            scores: Some(RarityScores {
+                statistical_rarity,
+                rarity_score,
+                information_content,
+                statistical_rarity_rank: rarer_statistical as i32 + 1,
+                rarity_score_rank: rarer_score as i32 + 1,
+                information_content_rank: rarer_information as i32 + 1,
+            }),
+        })
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RarityScoreModel {
+    StatisticalRarity,
+    #[default]
+    RarityScore,
+    InformationContent,
+}
+
+impl RarityScoreModel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::StatisticalRarity => "statistical_rarity",
+            Self::RarityScore => "rarity_score",
+            Self::InformationContent => "information_content",
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct RarityRanking {
+    pub collection_slug: String,
+    pub model: RarityScoreModel,
+    /// Rarest asset first
+    pub ranking: Vec<RarityScore>,
+}
+
+impl RarityRanking {
+    pub async fn make(
+        conn: &mut PgConnection,
+        collection_slug: &str,
+        model: RarityScoreModel,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Self> {
+        Ok(Self {
+            collection_slug: collection_slug.to_string(),
+            model,
+            ranking: read_rarity_ranking(conn, collection_slug, model.as_str(), limit, offset)
+                .await?,
         })
     }
 }
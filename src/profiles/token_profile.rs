@@ -1,17 +1,23 @@
 use super::{
-    collection_profile::CollectionProfile, liquidty_profile::LiquidityProfile,
-    price_profile::PriceProfile, rarity_profile::RarityProfile,
+    collection_profile::CollectionProfile,
+    liquidty_profile::{LiquidityProfile, LIQUIDITY_WINDOW_DAYS},
+    price_profile::PriceProfile,
+    rarity_profile::RarityProfile,
 };
+use crate::analyzers::fx::EthRates;
 use crate::analyzers::listings::*;
-use crate::analyzers::prices::get_most_valued_trait_floor;
-use crate::analyzers::rarities::get_trait_rarities;
+use crate::analyzers::strategies::PricingContext;
+use crate::analyzers::window::{Window, Windowed};
 use crate::from_wei;
-use crate::storage::read::{read_asset, read_assets_for_owner, read_listings_token_after_ts};
+use crate::storage::read::{read_asset, read_assets_for_owner, read_listings_token_between_ts};
 use crate::storage::Collection;
 use anyhow::Result;
-use chrono::{Duration, Utc};
+use chrono::{NaiveDateTime, Utc};
 use sqlx::PgConnection;
 
+/// Window of the listing count of collections without an analysis window
+pub static LISTINGS_WINDOW_DAYS: i64 = 30;
+
 #[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
 pub struct TokenProfile {
     pub opensea: String,
@@ -22,7 +28,9 @@ pub struct TokenProfile {
     pub token_id: i32,
     pub image_url: String,
     pub listing_price: Option<f64>,
-    pub nr_listings_30d: i32,
+    /// Listings over the window, the field names end in the window like `nr_listings_30d`
+    #[serde(flatten)]
+    pub listings: Windowed<TokenListings>,
     pub owner_tokens_in_collection: i32,
     pub collection_profile: CollectionProfile,
     pub price_profile: PriceProfile,
@@ -30,24 +38,36 @@ pub struct TokenProfile {
     pub rarity_profile: RarityProfile,
 }
 
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct TokenListings {
+    pub nr_listings: i32,
+}
+
 impl TokenProfile {
+    /// `window` overrides the windows of the collection's settings
     pub async fn make(
         conn: &mut PgConnection,
         collection: Collection,
         token_id: i32,
+        window: Option<Window>,
+        as_of: Option<NaiveDateTime>,
     ) -> Result<Self> {
         log::info!("Getting asset");
 
-        let collection_slug = collection.slug;
+        let ts = as_of.unwrap_or_else(|| Utc::now().naive_utc());
+        let listings_window = Window::resolve(window, &collection, LISTINGS_WINDOW_DAYS);
+        let liquidity_window = Window::resolve(window, &collection, LIQUIDITY_WINDOW_DAYS);
+
+        let collection_slug = collection.slug.clone();
 
         let asset = read_asset(conn, &collection_slug, token_id).await?;
 
         log::info!("Getting collection_address");
-        let collection_address = collection.address;
+        let collection_address = collection.address.clone();
 
         log::info!("Getting listing_price");
         let listing_price = if let Some(t) =
-            get_token_listings(conn, &collection_slug, vec![token_id])
+            get_token_listings(conn, &collection_slug, vec![token_id], &ts)
                 .await?
                 .first()
         {
@@ -56,41 +76,20 @@ impl TokenProfile {
             None
         };
 
-        let nr_listings_30d = read_listings_token_after_ts(
+        let nr_listings = read_listings_token_between_ts(
             conn,
             &collection_slug,
             token_id,
-            &(Utc::now() - Duration::days(30)).naive_utc(),
+            &listings_window.start(&ts),
+            &ts,
         )
         .await?
         .len() as i32;
 
-        let token_traits = get_trait_rarities(conn, &collection_slug, token_id).await?;
-
-        let rarest_trait = if let Some(t) = token_traits.first() {
-            t.trait_id.clone()
-        } else {
-            String::default()
-        };
-
-        let most_valuable_trait = get_most_valued_trait_floor(
-            conn,
-            &collection_slug,
-            token_traits.clone(),
-            collection.rarity_cutoff,
-        )
-        .await?;
-
-        let price_profile = PriceProfile::make(
-            conn,
-            &collection_slug,
-            token_id,
-            token_traits.clone(),
-            &rarest_trait,
-            &most_valuable_trait,
-            collection.rarity_cutoff,
-        )
-        .await?;
+        let ctx =
+            PricingContext::make(conn, &collection, Some(token_id), None, window, &ts).await?;
+        let price_profile = PriceProfile::make(conn, &collection, &ctx).await?;
+        let most_valuable_trait = ctx.most_valuable_trait.map(|t| t.trait_id);
 
         Ok(Self {
             opensea: format!(
@@ -104,29 +103,46 @@ impl TokenProfile {
             token_id,
             image_url: asset.image_url,
             listing_price: listing_price.map(from_wei),
-            nr_listings_30d,
+            listings: Windowed::new(listings_window, TokenListings { nr_listings }),
             owner_tokens_in_collection: read_assets_for_owner(conn, &collection_slug, &asset.owner)
                 .await?
                 .unwrap_or_default() as i32,
             liquidity_profile: LiquidityProfile::make(
                 conn,
                 &collection_slug,
-                token_id,
-                &rarest_trait,
+                &ctx.token_traits,
+                &ctx.rarest_trait,
+                price_profile.min_price,
+                price_profile.avg_price,
                 price_profile.max_price,
-                &most_valuable_trait.clone().map(|t| t.trait_id),
+                price_profile.top_bid,
+                &most_valuable_trait,
+                liquidity_window,
+                &ts,
             )
             .await?,
             price_profile,
-            collection_profile: CollectionProfile::make(conn, &collection_slug).await?,
+            collection_profile: CollectionProfile::make(conn, &collection_slug, window, as_of)
+                .await?,
             rarity_profile: RarityProfile::make(
                 conn,
                 &collection_slug,
                 token_id,
-                &rarest_trait,
-                &most_valuable_trait.clone().map(|t| t.trait_id),
+                &ctx.rarest_trait,
+                &most_valuable_trait,
             )
             .await?,
         })
     }
+
+    /// Converts the prices at the rate at `as_of`, the latest if None, and the collection's
+    /// averages at the rates of their sales
+    pub fn in_currency(mut self, rates: &EthRates, as_of: Option<&NaiveDateTime>) -> Self {
+        let rate = rates.rate(as_of);
+        self.listing_price = self.listing_price.map(|p| p * rate);
+        self.price_profile = self.price_profile.in_currency(rates.currency, rate);
+        self.liquidity_profile = self.liquidity_profile.in_currency(rate);
+        self.collection_profile = self.collection_profile.in_currency(rates, as_of);
+        self
+    }
 }
@@ -0,0 +1,300 @@
+use crate::analyzers::fx::{Currency, EthRates};
+use crate::analyzers::window::{Window, Windowed};
+use crate::from_wei;
+use crate::storage::read::{
+    read_collection, read_listed_for_collection_at_ts, read_sales_for_collection_after_ts,
+    read_trait_index, read_traits_for_collection,
+};
+use crate::storage::SaleEvent;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::PgConnection;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Window of the trait sales of collections without an analysis window
+pub static TRAIT_SALES_WINDOW_DAYS: i64 = 30;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct TraitProfile {
+    pub trait_id: String,
+    pub trait_type: String,
+    pub trait_name: String,
+    pub trait_count: i32,
+    pub rarity: f64,
+    pub floor_price: Option<f64>,
+    pub nr_listed: usize,
+    pub last_sale_price: Option<f64>,
+    pub last_sale_time: Option<NaiveDateTime>,
+    /// Sales over the window, the field names end in the window like `nr_sales_30d`
+    #[serde(flatten)]
+    pub sales: Windowed<TraitSales>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct TraitSales {
+    pub nr_sales: usize,
+    pub avg_price: Option<f64>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TraitSort {
+    #[default]
+    Rarity,
+    TraitCount,
+    FloorPrice,
+    NrListed,
+    LastSalePrice,
+    /// Sales over the window
+    #[serde(alias = "nr_sales_30d")]
+    NrSales,
+    #[serde(alias = "avg_price_30d")]
+    AvgPrice,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Floor, listings and sales of every trait of a collection
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct TraitLadder {
+    pub collection_slug: String,
+    pub traits: Vec<TraitProfile>,
+    pub currency: Currency,
+}
+
+impl TraitLadder {
+    /// Floors are converted at the latest rate and every sale at the rate of its time
+    pub async fn make(
+        conn: &mut PgConnection,
+        collection_slug: &str,
+        sort: TraitSort,
+        order: SortOrder,
+        window: Option<Window>,
+        rates: &EthRates,
+        ts: &NaiveDateTime,
+    ) -> Result<Self> {
+        let collection = read_collection(conn, collection_slug).await?;
+        let window = Window::resolve(window, &collection, TRAIT_SALES_WINDOW_DAYS);
+        let traits = read_traits_for_collection(conn, collection_slug).await?;
+
+        let listings: HashMap<i32, f64> =
+            read_listed_for_collection_at_ts(conn, collection_slug, ts)
+                .await?
+                .into_iter()
+                .filter_map(|l| {
+                    l.price
+                        .map(|p| (l.token_id, from_wei(p) * rates.rate(None)))
+                })
+                .collect();
+
+        let mut sales_by_token = HashMap::<i32, Vec<SaleEvent>>::new();
+        for s in read_sales_for_collection_after_ts(
+            conn,
+            collection_slug,
+            &NaiveDateTime::from_timestamp(0, 0),
+        )
+        .await?
+        .into_iter()
+        .filter(|s| (s.timestamp as i64) < ts.timestamp())
+        {
+            sales_by_token.entry(s.token_id).or_default().push(s);
+        }
+        let start = window.start(ts).timestamp();
+        let sale_price = |s: &SaleEvent| {
+            from_wei(s.price)
+                * rates.rate(Some(&NaiveDateTime::from_timestamp(s.timestamp as i64, 0)))
+        };
+
+        let mut profiles = traits
+            .into_iter()
+            .map(|t| {
+                let listed = t
+                    .token_ids
+                    .iter()
+                    .filter_map(|id| listings.get(id).copied())
+                    .collect::<Vec<_>>();
+                let trait_sales = t
+                    .token_ids
+                    .iter()
+                    .filter_map(|id| sales_by_token.get(id))
+                    .flatten()
+                    .collect::<Vec<_>>();
+                let last_sale = trait_sales.iter().max_by_key(|s| s.timestamp);
+                let window_sales = trait_sales
+                    .iter()
+                    .filter(|s| (s.timestamp as i64) > start)
+                    .map(|s| sale_price(s))
+                    .collect::<Vec<_>>();
+
+                TraitProfile {
+                    rarity: t.trait_count as f64 / collection.total_supply as f64,
+                    floor_price: listed.iter().copied().reduce(f64::min),
+                    nr_listed: listed.len(),
+                    last_sale_price: last_sale.map(|s| sale_price(s)),
+                    last_sale_time: last_sale
+                        .map(|s| NaiveDateTime::from_timestamp(s.timestamp as i64, 0)),
+                    sales: Windowed::new(
+                        window,
+                        TraitSales {
+                            nr_sales: window_sales.len(),
+                            avg_price: if window_sales.is_empty() {
+                                None
+                            } else {
+                                Some(window_sales.iter().sum::<f64>() / window_sales.len() as f64)
+                            },
+                        },
+                    ),
+                    trait_id: t.trait_id,
+                    trait_type: t.trait_type,
+                    trait_name: t.trait_name,
+                    trait_count: t.trait_count,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        sort_traits(&mut profiles, sort, order);
+
+        Ok(Self {
+            collection_slug: collection_slug.to_string(),
+            traits: profiles,
+            currency: rates.currency,
+        })
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct TraitDay {
+    pub timestamp: NaiveDateTime,
+    /// Cheapest listing at the end of the day
+    pub floor_price: Option<f64>,
+    pub median_price: Option<f64>,
+    pub avg_price: Option<f64>,
+    pub volume: f64,
+    pub nr_sales: i32,
+}
+
+/// Daily index of a trait, oldest first
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct TraitHistory {
+    pub collection_slug: String,
+    pub trait_id: String,
+    pub history: Vec<TraitDay>,
+    pub currency: Currency,
+}
+
+impl TraitHistory {
+    /// Every day is converted at the rate of its own time
+    pub async fn make(
+        conn: &mut PgConnection,
+        collection_slug: &str,
+        trait_id: &str,
+        since: &NaiveDateTime,
+        currency: Currency,
+    ) -> Result<Self> {
+        let index = read_trait_index(conn, collection_slug, trait_id, since).await?;
+        let rates = EthRates::load(conn, currency).await?;
+
+        let mut history = vec![];
+        for i in index {
+            let timestamp = NaiveDateTime::from_timestamp(i.timestamp as i64, 0);
+            let rate = rates.rate(Some(&timestamp));
+
+            history.push(TraitDay {
+                timestamp,
+                floor_price: i.floor_price.map(|p| p * rate),
+                median_price: i.median_price.map(|p| p * rate),
+                avg_price: i.avg_price.map(|p| p * rate),
+                volume: i.volume * rate,
+                nr_sales: i.nr_sales,
+            });
+        }
+
+        Ok(Self {
+            collection_slug: collection_slug.to_string(),
+            trait_id: trait_id.to_string(),
+            history,
+            currency,
+        })
+    }
+}
+
+/// Traits without a value for the sort key always go last
+fn sort_traits(traits: &mut [TraitProfile], sort: TraitSort, order: SortOrder) {
+    let key = |t: &TraitProfile| -> Option<f64> {
+        match sort {
+            TraitSort::Rarity => Some(t.rarity),
+            TraitSort::TraitCount => Some(t.trait_count as f64),
+            TraitSort::FloorPrice => t.floor_price,
+            TraitSort::NrListed => Some(t.nr_listed as f64),
+            TraitSort::LastSalePrice => t.last_sale_price,
+            TraitSort::NrSales => Some(t.sales.values.nr_sales as f64),
+            TraitSort::AvgPrice => t.sales.values.avg_price,
+        }
+    };
+
+    traits.sort_by(|a, b| match (key(a), key(b)) {
+        (Some(a), Some(b)) => match order {
+            SortOrder::Asc => a.partial_cmp(&b).unwrap(),
+            SortOrder::Desc => b.partial_cmp(&a).unwrap(),
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(trait_id: &str, floor_price: Option<f64>) -> TraitProfile {
+        TraitProfile {
+            trait_id: trait_id.to_string(),
+            trait_type: String::new(),
+            trait_name: String::new(),
+            trait_count: 1,
+            rarity: 0.1,
+            floor_price,
+            nr_listed: 0,
+            last_sale_price: None,
+            last_sale_time: None,
+            sales: Windowed::new(
+                Window::days(30),
+                TraitSales {
+                    nr_sales: 0,
+                    avg_price: None,
+                },
+            ),
+        }
+    }
+
+    #[test]
+    fn test_sort_traits() {
+        let mut traits = vec![
+            profile("a", None),
+            profile("b", Some(1.0)),
+            profile("c", Some(2.0)),
+        ];
+
+        sort_traits(&mut traits, TraitSort::FloorPrice, SortOrder::Desc);
+        let ids = traits
+            .iter()
+            .map(|t| t.trait_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["c", "b", "a"]);
+
+        sort_traits(&mut traits, TraitSort::FloorPrice, SortOrder::Asc);
+        let ids = traits
+            .iter()
+            .map(|t| t.trait_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["b", "c", "a"]);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::analyzers::fx::{Currency, EthRates};
+use crate::storage::read::read_price_snapshots_for_token;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct Valuation {
+    pub timestamp: NaiveDateTime,
+    pub strategies: HashMap<String, Option<f64>>,
+    pub min_price: f64,
+    pub avg_price: f64,
+    pub max_price: f64,
+    pub confidence: f64,
+}
+
+/// Stored valuations of a token, oldest first
+#[derive(Debug, serde::Serialize, serde::Deserialize, rweb::Schema, Clone)]
+pub struct ValuationProfile {
+    pub collection_slug: String,
+    pub token_id: i32,
+    pub history: Vec<Valuation>,
+    pub currency: Currency,
+}
+
+impl ValuationProfile {
+    /// Every valuation is converted at the rate of its own time
+    pub async fn make(
+        conn: &mut PgConnection,
+        collection_slug: &str,
+        token_id: i32,
+        since: &NaiveDateTime,
+        currency: Currency,
+    ) -> Result<Self> {
+        let snapshots =
+            read_price_snapshots_for_token(conn, collection_slug, token_id, since).await?;
+        let rates = EthRates::load(conn, currency).await?;
+
+        let mut history = vec![];
+        for s in snapshots {
+            let timestamp = NaiveDateTime::from_timestamp(s.timestamp as i64, 0);
+            let rate = rates.rate(Some(&timestamp));
+
+            history.push(Valuation {
+                timestamp,
+                strategies: s
+                    .strategies
+                    .0
+                    .into_iter()
+                    .map(|(k, v)| (k, v.map(|p| p * rate)))
+                    .collect(),
+                min_price: s.min_price * rate,
+                avg_price: s.avg_price * rate,
+                max_price: s.max_price * rate,
+                confidence: s.confidence,
+            });
+        }
+
+        Ok(Self {
+            collection_slug: collection_slug.to_string(),
+            token_id,
+            history,
+            currency,
+        })
+    }
+}
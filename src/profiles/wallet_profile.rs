@@ -1,6 +1,7 @@
 use super::price_profile::PriceProfile;
-use crate::analyzers::wallet::get_value_for_wallet;
-use crate::storage::read::read_asset;
+use crate::analyzers::fx::Currency;
+use crate::analyzers::wallet::{get_value_for_wallet, get_wallet_token_ids, Ownership};
+use crate::storage::read::{read_asset, read_collection};
 use anyhow::Result;
 use sqlx::PgPool;
 use std::collections::HashMap;
@@ -18,6 +19,8 @@ pub struct WalletProfile {
     pub total_value_min: f64,
     pub total_value_avg: f64,
     pub tokens: HashMap<String, TokensInner>,
+    pub ownership: Ownership,
+    pub currency: Currency,
 }
 
 impl WalletProfile {
@@ -29,8 +32,10 @@ impl WalletProfile {
         offset: i64,
     ) -> Result<Self> {
         let mut conn = pool.acquire().await?;
-        let (value_max, value_min, value_avg, address, profiles, total_tokens) =
-            get_value_for_wallet(pool, collection_slug, wallet, limit, offset).await?;
+        let collection = read_collection(&mut conn, collection_slug).await?;
+        let (ids, ownership) = get_wallet_token_ids(&mut conn, &collection, wallet).await?;
+        let (value_max, value_min, value_avg, address, profiles, total_tokens, _) =
+            get_value_for_wallet(pool, &collection, ids, limit, offset).await?;
 
         let mut tokens = HashMap::<String, TokensInner>::new();
         for (t, p) in profiles {
@@ -51,6 +56,8 @@ impl WalletProfile {
             total_value_min: value_min,
             total_value_avg: value_avg,
             tokens,
+            ownership,
+            currency: Currency::Eth,
         })
     }
 
@@ -61,8 +68,11 @@ impl WalletProfile {
         limit: i64,
         offset: i64,
     ) -> Result<Self> {
-        let (value_max, value_min, value_avg, address, profiles, total_tokens) =
-            get_value_for_wallet(pool, collection_slug, wallet, limit, offset).await?;
+        let mut conn = pool.acquire().await?;
+        let collection = read_collection(&mut conn, collection_slug).await?;
+        let (ids, ownership) = get_wallet_token_ids(&mut conn, &collection, wallet).await?;
+        let (value_max, value_min, value_avg, address, profiles, total_tokens, _) =
+            get_value_for_wallet(pool, &collection, ids, limit, offset).await?;
 
         let mut tokens = HashMap::<String, TokensInner>::new();
         for (t, p) in profiles {
@@ -82,6 +92,25 @@ impl WalletProfile {
             total_value_min: value_min,
             total_value_avg: value_avg,
             tokens,
+            ownership,
+            currency: Currency::Eth,
         })
     }
+
+    /// Converts the wallet totals and the price profile of every token to `currency`
+    pub fn in_currency(mut self, currency: Currency, rate: f64) -> Self {
+        self.total_value_max *= rate;
+        self.total_value_min *= rate;
+        self.total_value_avg *= rate;
+        self.tokens = self
+            .tokens
+            .into_iter()
+            .map(|(id, mut t)| {
+                t.price_profile = t.price_profile.in_currency(currency, rate);
+                (id, t)
+            })
+            .collect();
+        self.currency = currency;
+        self
+    }
 }
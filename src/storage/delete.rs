@@ -32,6 +32,15 @@ pub async fn purge_collection(conn: &mut PgConnection, collection: &str) -> Resu
     .execute(&mut txn)
     .await?;
 
+    sqlx::query!(
+        r#"
+       delete from asset_overlap where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
     sqlx::query!(
         r#"
        delete from sale where collection_slug = $1; 
@@ -50,5 +59,68 @@ pub async fn purge_collection(conn: &mut PgConnection, collection: &str) -> Resu
     .execute(&mut txn)
     .await?;
 
+    sqlx::query!(
+        r#"
+       delete from backtest_result where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+       delete from hedonic_model where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+       delete from trait_premium where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+       delete from price_snapshot where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+       delete from trait_index where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+       delete from bid where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+       delete from rarity_score where collection_slug = $1;
+       "#,
+        collection
+    )
+    .execute(&mut txn)
+    .await?;
+
     txn.commit().await.map_err(|e| e.into())
 }
@@ -21,12 +21,16 @@ pub struct Asset {
     pub owner: String,
     pub traits: Vec<String>,
     pub unique_traits: i32,
-    pub traits_3_combination_overlap: i32,
-    pub traits_4_combination_overlap: i32,
-    pub traits_5_combination_overlap: i32,
-    pub traits_3_combination_overlap_ids: Vec<i32>,
-    pub traits_4_combination_overlap_ids: Vec<i32>,
-    pub traits_5_combination_overlap_ids: Vec<i32>,
+}
+
+/// Tokens sharing all traits of at least one `combination_size` combination of the asset's traits
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct AssetOverlap {
+    pub collection_slug: String,
+    pub token_id: i32,
+    pub combination_size: i32,
+    pub nr_overlapping: i32,
+    pub overlapping_ids: Vec<i32>,
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -39,6 +43,20 @@ pub struct Collection {
     pub rarity_cutoff: f64,
     pub ignored_trait_types_rarity: Vec<String>,
     pub ignored_trait_types_overlap: Vec<String>,
+    /// Sizes of the trait combinations to compute overlaps for
+    pub overlap_sizes: Vec<i32>,
+    pub pricing_strategies: Vec<String>,
+    /// Use sales flagged as wash trades or outliers in the analyzers
+    pub include_flagged_sales: bool,
+    /// Days of the windowed profile fields and price averages, each their own default if None
+    pub analysis_window_days: Option<i32>,
+    /// Last sync of transfers and sales, asset owners are current as of then
+    pub owners_synced_at: Option<i32>,
+    /// All fees taken from the seller, marketplace fee and royalty together
+    pub seller_fee_basis_points: i32,
+    pub opensea_seller_fee_basis_points: i32,
+    /// Royalty paid to the creator
+    pub dev_seller_fee_basis_points: i32,
     pub banner_image_url: String,
     pub daily_volume: f64,
     pub daily_sales: f64,
@@ -72,6 +90,110 @@ pub struct SaleEvent {
     pub token_id: i32,
     pub timestamp: i32,
     pub price: f64,
+    pub buyer: Option<String>,
+    pub seller: Option<String>,
+    /// Why the sale is excluded from the analyzers, None for clean sales
+    pub flag: Option<String>,
+    /// Symbol of the currency the sale was paid in, `price` is always in ETH
+    pub payment_token: String,
+    /// Raw amount paid in `payment_token`
+    pub payment_amount: f64,
+    pub payment_decimals: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, rweb::Schema, Clone)]
+pub struct BacktestResult {
+    pub collection_slug: String,
+    pub strategy: String,
+    pub nr_sales: i32,
+    pub nr_priced: i32,
+    pub mae: Option<f64>,
+    pub mape: Option<f64>,
+    pub hit_rate: Option<f64>,
+    pub timestamp: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct HedonicModel {
+    pub collection_slug: String,
+    pub intercept: f64,
+    pub market_coefficient: f64,
+    pub r_squared: f64,
+    pub nr_sales: i32,
+    pub timestamp: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct TraitPremium {
+    pub collection_slug: String,
+    pub trait_id: String,
+    pub premium: f64,
+    /// Timestamp of the model the premium belongs to
+    pub timestamp: i32,
+}
+
+/// Floor and sales of a trait during one UTC day, prices in ETH
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct TraitIndex {
+    pub collection_slug: String,
+    pub trait_id: String,
+    /// Start of the day
+    pub timestamp: i32,
+    /// Cheapest listing at the end of the day
+    pub floor_price: Option<f64>,
+    pub median_price: Option<f64>,
+    pub avg_price: Option<f64>,
+    pub volume: f64,
+    pub nr_sales: i32,
+}
+
+/// Rarity of an asset under every model, rank 1 is the rarest asset of the collection
+#[derive(serde::Serialize, serde::Deserialize, Debug, rweb::Schema, Clone)]
+pub struct RarityScore {
+    pub collection_slug: String,
+    pub token_id: i32,
+    /// Product of the trait frequencies, lower is rarer
+    pub statistical_rarity: f64,
+    /// Sum of the inverse trait frequencies
+    pub rarity_score: f64,
+    /// Sum of the information content of the traits in bits
+    pub information_content: f64,
+    pub statistical_rarity_rank: i32,
+    pub rarity_score_rank: i32,
+    pub information_content_rank: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct PriceSnapshot {
+    pub collection_slug: String,
+    pub token_id: i32,
+    pub timestamp: i32,
+    /// Price of every strategy by name
+    pub strategies: sqlx::types::Json<HashMap<String, Option<f64>>>,
+    pub min_price: f64,
+    pub avg_price: f64,
+    pub max_price: f64,
+    pub confidence: f64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, rweb::Schema, Clone)]
+pub struct FxRate {
+    pub timestamp: i32,
+    /// Price of one ETH in USD
+    pub eth_usd: f64,
+}
+
+/// An offer on a token, on every token having `trait_id` or, without either, on any token
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Bid {
+    pub collection_slug: String,
+    pub update_type: String,
+    pub token_id: Option<i32>,
+    pub trait_id: Option<String>,
+    pub bidder: String,
+    pub price: Option<f64>,
+    pub timestamp: i32,
+    pub expires_at: Option<i32>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -86,6 +208,7 @@ pub struct Listing {
 use dotenv::dotenv;
 use sqlx::pool::PoolOptions;
 use sqlx::PgPool;
+use std::collections::HashMap;
 use std::env;
 
 pub async fn establish_connection() -> PgPool {
@@ -1,13 +1,83 @@
-use super::Asset;
+use super::{Asset, AssetOverlap};
 use crate::opensea::types::Asset as OpenseaAsset;
-use crate::storage::read::read_traits_overlaping_tokens;
-use anyhow::Result;
+use crate::storage::read::{read_traits_for_collection, read_traits_overlaping_tokens};
+use anyhow::{anyhow, Result};
 use chrono::Utc;
-use futures::StreamExt;
 use itertools::Itertools;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use std::collections::{HashMap, HashSet};
 
+/// Combination sizes of collections that do not configure their own
+pub static DEFAULT_OVERLAP_SIZES: [i32; 3] = [3, 4, 5];
+/// Larger combinations are rarely shared and their number grows quickly
+pub static MAX_OVERLAP_SIZE: i32 = 8;
+
+/// Tokens of every trait as a bitset, bit `i` stands for the `i`th lowest token id
+pub struct TraitBitsetIndex {
+    token_ids: Vec<i32>,
+    traits: HashMap<String, Vec<u64>>,
+}
+
+impl TraitBitsetIndex {
+    pub fn new(token_mapping: &HashMap<String, Vec<i32>>) -> Self {
+        let token_ids = token_mapping
+            .values()
+            .flatten()
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .sorted()
+            .collect::<Vec<_>>();
+        let positions = token_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect::<HashMap<_, _>>();
+        let nr_words = token_ids.len().div_ceil(64);
+
+        let traits = token_mapping
+            .iter()
+            .map(|(trait_id, ids)| {
+                let mut bits = vec![0u64; nr_words];
+                for id in ids {
+                    let i = positions[id];
+                    bits[i / 64] |= 1 << (i % 64);
+                }
+                (trait_id.clone(), bits)
+            })
+            .collect();
+
+        Self { token_ids, traits }
+    }
+
+    /// Same as `get_combination_overlap`, without a query per combination
+    pub fn combination_overlap(&self, traits: &[String], size: usize) -> HashSet<i32> {
+        let mut overlap = vec![0u64; self.token_ids.len().div_ceil(64)];
+        for vpair in traits.iter().combinations(size) {
+            // a trait without tokens makes the whole combination empty
+            let mut bitsets = vpair.into_iter().map(|t| self.traits.get(t));
+            let mut shared = match bitsets.next().flatten() {
+                Some(b) => b.clone(),
+                None => continue,
+            };
+            for bits in bitsets {
+                match bits {
+                    Some(b) => shared.iter_mut().zip(b).for_each(|(s, b)| *s &= b),
+                    None => shared.fill(0),
+                }
+            }
+            overlap.iter_mut().zip(&shared).for_each(|(o, s)| *o |= s);
+        }
+
+        self.token_ids
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| overlap[i / 64] & (1 << (i % 64)) != 0)
+            .map(|(_, id)| *id)
+            .collect()
+    }
+}
+
 pub async fn generate_token_mapping(
     os_assets: Vec<OpenseaAsset>,
 ) -> Result<HashMap<String, Vec<i32>>> {
@@ -35,16 +105,7 @@ pub async fn generate_token_mapping(
             .collect::<Vec<String>>();
 
         for t in trait_ids {
-            match map.get(&t) {
-                Some(l) => {
-                    let mut nl = l.clone();
-                    nl.push(asset.token_id);
-                    map.insert(t.clone(), nl);
-                }
-                None => {
-                    map.insert(t.clone(), vec![]);
-                }
-            }
+            map.entry(t).or_default().push(asset.token_id);
         }
     }
 
@@ -58,7 +119,9 @@ pub async fn process_assets(
     os_assets: Vec<OpenseaAsset>,
     collection_slug: &str,
     ignored_trait_types_overlap: Vec<String>,
-) -> Result<Vec<Asset>> {
+    token_mapping: &HashMap<String, Vec<i32>>,
+    overlap_sizes: &[i32],
+) -> Result<(Vec<Asset>, Vec<AssetOverlap>)> {
     let mut assets: Vec<Asset> = vec![];
     for asset in os_assets {
         let trait_list = asset
@@ -100,106 +163,115 @@ pub async fn process_assets(
             owner: asset.owner.address,
             traits: trait_ids,
             unique_traits: unique_traits as i32,
-            traits_3_combination_overlap: 0i32,
-            traits_4_combination_overlap: 0i32,
-            traits_5_combination_overlap: 0i32,
-            traits_3_combination_overlap_ids: vec![],
-            traits_4_combination_overlap_ids: vec![],
-            traits_5_combination_overlap_ids: vec![],
         });
     }
     println!("base processing done");
 
     let b = Utc::now();
 
-    let mut res = vec![];
-    let chunks: Vec<&[Asset]> = assets.chunks(assets.len() / 16).collect();
-
-    let mut stream = futures::stream::iter(0..chunks.len())
-        .map(|i| {
-            compute_combinations(
-                pool.clone(),
-                collection_slug,
-                chunks[i].clone().to_vec(),
-                &ignored_trait_types_overlap,
-            )
-        })
-        .buffer_unordered(16);
-
-    while let Some(result) = stream.next().await {
-        match result {
-            Ok(mut resp) => {
-                res.append(&mut resp);
-            }
-            Err(e) => {
-                println!("Error: {:?}", e);
-            }
-        }
-    }
+    // only stored traits have tokens to overlap with
+    let mut conn = pool.acquire().await?;
+    let stored_traits = read_traits_for_collection(&mut conn, collection_slug)
+        .await?
+        .into_iter()
+        .map(|t| t.trait_id)
+        .collect::<HashSet<_>>();
+    let index = TraitBitsetIndex::new(
+        &token_mapping
+            .iter()
+            .filter(|(t, _)| stored_traits.contains(*t))
+            .map(|(t, ids)| (t.clone(), ids.clone()))
+            .collect(),
+    );
+
+    // the threads would otherwise block the runtime until all combinations are counted
+    let overlap_sizes = overlap_sizes.to_vec();
+    let (assets, overlaps) = tokio::task::spawn_blocking(move || {
+        let chunk_size = (assets.len() / num_cpus::get()).max(1);
+        let overlaps = std::thread::scope(|s| {
+            assets
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let index = &index;
+                    let ignored = &ignored_trait_types_overlap;
+                    let sizes = &overlap_sizes;
+                    s.spawn(move || compute_combinations(index, chunk, ignored, sizes))
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|h| {
+                    h.join()
+                        .map_err(|_| anyhow!("computing trait combination overlaps panicked"))
+                })
+                .collect::<Result<Vec<_>>>()
+        })?;
+        Ok::<_, anyhow::Error>((assets, overlaps.into_iter().flatten().collect::<Vec<_>>()))
+    })
+    .await??;
 
     let a = Utc::now();
     println!("elapsed {:?}s", (a - b).num_seconds());
 
-    Ok(res)
+    Ok((assets, overlaps))
 }
 
-async fn compute_combinations(
-    pool: PgPool,
-    collection_slug: &str,
-    assets: Vec<Asset>,
+fn compute_combinations(
+    index: &TraitBitsetIndex,
+    assets: &[Asset],
     ignored_trait_types_overlap: &[String],
-) -> Result<Vec<Asset>> {
+    overlap_sizes: &[i32],
+) -> Vec<AssetOverlap> {
     let mut res = vec![];
 
-    let mut conn = pool.acquire().await?;
-
-    for mut asset in assets {
-        let mut unique_3 = HashSet::<i32>::new();
-        let mut unique_4 = HashSet::<i32>::new();
-        let mut unique_5 = HashSet::<i32>::new();
-
+    for asset in assets {
         let asset_traits: Vec<_> = asset
             .traits
-            .clone()
             .iter()
-            .filter(|a| !ignored_trait_types_overlap.contains(a))
+            .filter(|t| {
+                !ignored_trait_types_overlap
+                    .iter()
+                    .any(|i| t.starts_with(&format!("{}:", i)))
+            })
             .cloned()
             .collect();
 
-        for vpair in asset_traits.iter().combinations(3) {
-            let traits = vpair.into_iter().map(|t| t.to_string()).collect::<Vec<_>>();
-
-            let ids = read_traits_overlaping_tokens(&mut conn, collection_slug, &traits).await?;
-            unique_3.extend(ids);
+        for size in overlap_sizes {
+            let overlapping_ids = index
+                .combination_overlap(&asset_traits, *size as usize)
+                .into_iter()
+                .sorted()
+                .collect::<Vec<_>>();
+
+            res.push(AssetOverlap {
+                collection_slug: asset.collection_slug.clone(),
+                token_id: asset.token_id,
+                combination_size: *size,
+                nr_overlapping: overlapping_ids.len() as i32,
+                overlapping_ids,
+            });
         }
-
-        for vpair in asset_traits.iter().combinations(4) {
-            let traits = vpair.into_iter().map(|t| t.to_string()).collect::<Vec<_>>();
-
-            let ids = read_traits_overlaping_tokens(&mut conn, collection_slug, &traits).await?;
-            unique_4.extend(ids);
-        }
-
-        for vpair in asset_traits.iter().combinations(5) {
-            let traits = vpair.into_iter().map(|t| t.to_string()).collect::<Vec<_>>();
-
-            let ids = read_traits_overlaping_tokens(&mut conn, collection_slug, &traits).await?;
-            unique_5.extend(ids);
-        }
-
-        asset.traits_3_combination_overlap = unique_3.len() as i32;
-        asset.traits_4_combination_overlap = unique_4.len() as i32;
-        asset.traits_5_combination_overlap = unique_5.len() as i32;
-        asset.traits_3_combination_overlap_ids = unique_3.into_iter().collect::<Vec<_>>();
-        asset.traits_4_combination_overlap_ids = unique_4.into_iter().collect::<Vec<_>>();
-        asset.traits_5_combination_overlap_ids = unique_5.into_iter().collect::<Vec<_>>();
-
-        res.push(asset.clone());
     }
 
     println!("post processing done");
 
-    Ok(res)
+    res
+}
+
+/// Tokens sharing all traits of at least one `size` combination of `traits`
+pub async fn get_combination_overlap(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    traits: &[String],
+    size: usize,
+) -> Result<HashSet<i32>> {
+    let mut overlap = HashSet::<i32>::new();
+    for vpair in traits.iter().combinations(size) {
+        let traits = vpair.into_iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        let ids = read_traits_overlaping_tokens(conn, collection_slug, &traits).await?;
+        overlap.extend(ids);
+    }
+    Ok(overlap)
 }
 
 #[cfg(test)]
@@ -342,4 +414,60 @@ mod tests {
         println!("{}", serde_json::to_string_pretty(&a).unwrap());
         assert!(!a.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_trait_bitset_index() {
+        let mapping = generate_token_mapping(get_assets()).await.unwrap();
+        let index = TraitBitsetIndex::new(&mapping);
+        let traits = |id: i32| {
+            mapping
+                .iter()
+                .filter(|(_, ids)| ids.contains(&id))
+                .map(|(t, _)| t.clone())
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+
+        // background, body and rune are shared with token 2
+        assert_eq!(
+            index.combination_overlap(&traits(1), 3),
+            HashSet::from([1, 2])
+        );
+        assert_eq!(index.combination_overlap(&traits(1), 4), HashSet::from([1]));
+        assert_eq!(
+            index.combination_overlap(&traits(2), 4),
+            HashSet::from([2, 3])
+        );
+        assert_eq!(index.combination_overlap(&traits(3), 5), HashSet::from([3]));
+        assert!(index
+            .combination_overlap(&["background:white".to_string()], 1)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_compute_combinations() {
+        let mapping = generate_token_mapping(get_assets()).await.unwrap();
+        let index = TraitBitsetIndex::new(&mapping);
+        let asset = crate::storage::Asset {
+            name: String::from("Test"),
+            collection_slug: String::from("test"),
+            token_id: 1,
+            image_url: String::from("Test"),
+            owner: String::from("addr"),
+            traits: mapping
+                .iter()
+                .filter(|(_, ids)| ids.contains(&1))
+                .map(|(t, _)| t.clone())
+                .collect(),
+            unique_traits: 0,
+        };
+
+        let overlaps = compute_combinations(&index, &[asset], &["background".to_string()], &[2, 3]);
+
+        // without the background only body and rune are shared, with tokens 2 and 3
+        assert_eq!(overlaps.len(), 2);
+        assert_eq!(overlaps[0].combination_size, 2);
+        assert_eq!(overlaps[0].overlapping_ids, vec![1, 2, 3]);
+        assert_eq!(overlaps[1].nr_overlapping, 1);
+    }
 }
@@ -63,6 +63,49 @@ pub async fn read_trait(
     .map_err(|e| e.into())
 }
 
+pub async fn read_traits_for_collection(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Vec<Trait>> {
+    sqlx::query_as!(
+        Trait,
+        r#"
+            select
+                *
+            from
+                trait t
+            where t.collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn read_traits_by_ids(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    trait_ids: &[String],
+) -> Result<HashMap<String, i32>> {
+    Ok(sqlx::query!(
+        r#"
+            select
+                trait_id, trait_count
+            from
+                trait
+            where collection_slug = $1 and trait_id = any($2)
+        "#,
+        collection_slug,
+        trait_ids,
+    )
+    .map(|r| (r.trait_id, r.trait_count))
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .collect())
+}
+
 pub async fn read_traits_for_asset(
     conn: &mut PgConnection,
     collection_slug: &str,
@@ -147,6 +190,30 @@ pub async fn read_asset(
     .map_err(|e| e.into())
 }
 
+/// Overlaps of the asset for every computed combination size, smallest size first
+pub async fn read_asset_overlaps(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_id: i32,
+) -> Result<Vec<AssetOverlap>> {
+    sqlx::query_as!(
+        AssetOverlap,
+        r#"
+            select
+                *
+            from
+                asset_overlap
+            where collection_slug = $1 and token_id = $2
+            order by combination_size asc
+        "#,
+        collection_slug,
+        token_id,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
 pub async fn read_assets_with_trait(
     conn: &mut PgConnection,
     collection_slug: &str,
@@ -191,6 +258,72 @@ pub async fn read_assets_with_traits(
     .map_err(|e| e.into())
 }
 
+pub async fn read_asset_traits_for_collection(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<HashMap<i32, Vec<String>>> {
+    let vals = sqlx::query!(
+        r#"
+            select
+                a.token_id, array_agg(t.trait_id) as "traits!"
+            from
+                asset as a join trait as t
+                on
+                    t.collection_slug = a.collection_slug and t.trait_id = any(a.traits)
+            where a.collection_slug = $1
+            group by a.token_id
+        "#,
+        collection_slug,
+    )
+    .map(|r| (r.token_id, r.traits))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(vals.into_iter().collect())
+}
+
+/// Traits as stored on the assets, traits left out of the trait table included
+pub async fn read_all_asset_traits_for_collection(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<HashMap<i32, Vec<String>>> {
+    let vals = sqlx::query!(
+        r#"
+            select
+                token_id, traits
+            from
+                asset
+            where collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .map(|r| (r.token_id, r.traits))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(vals.into_iter().collect())
+}
+
+pub async fn read_token_ids_for_collection(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Vec<i32>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                token_id
+            from
+                asset
+            where collection_slug = $1
+            order by token_id
+        "#,
+        collection_slug,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
 pub async fn read_assets_for_owner(
     conn: &mut PgConnection,
     collection_slug: &str,
@@ -213,6 +346,44 @@ pub async fn read_assets_for_owner(
     .map_err(|e| e.into())
 }
 
+pub async fn read_token_ids_for_owner(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    owner_address: &str,
+) -> Result<Vec<i32>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                token_id
+            from
+                asset
+            where collection_slug = $1 and lower(owner) = lower($2)
+            order by token_id
+        "#,
+        collection_slug,
+        owner_address,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn read_nr_assets(conn: &mut PgConnection, collection_slug: &str) -> Result<i64> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                count(*) as "count!"
+            from
+                asset
+            where collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
 // ============ Sales ============
 pub async fn read_sales_for_trait(
     conn: &mut PgConnection,
@@ -232,7 +403,7 @@ pub async fn read_sales_for_trait(
                 from
                     asset a     
                 where a.collection_slug = $1 and  $2 = any(a.traits)
-            )
+            ) and valid_sale(flag, collection_slug)
         "#,
         collection_slug,
         trait_id,
@@ -272,7 +443,7 @@ pub async fn read_highest_sale_for_collection(
                     token_id
                 from
                     sale
-                where collection_slug = $1 
+                where collection_slug = $1 and valid_sale(flag, collection_slug)
                 group by price,token_id order by price desc
             "#,
         collection_slug,
@@ -282,11 +453,12 @@ pub async fn read_highest_sale_for_collection(
     .map_err(|e| e.into())
 }
 
-pub async fn read_sales_for_collection_above_price_after_ts(
+pub async fn read_sales_for_collection_above_price_between_ts(
     conn: &mut PgConnection,
     collection_slug: &str,
     price: f64,
-    timestamp: &NaiveDateTime,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
 ) -> Result<Vec<SaleEvent>> {
     sqlx::query_as!(
         SaleEvent,
@@ -295,12 +467,14 @@ pub async fn read_sales_for_collection_above_price_after_ts(
                     *
                 from
                     sale
-                where collection_slug = $1 and price > $2 and timestamp > $3
+                where collection_slug = $1 and price > $2 and timestamp > $3 and timestamp < $4
+                and valid_sale(flag, collection_slug)
                 order by price asc
             "#,
         collection_slug,
         price * 10f64.powf(18f64),
-        timestamp.timestamp() as i32,
+        from.timestamp() as i32,
+        to.timestamp() as i32,
     )
     .fetch_all(&mut *conn)
     .await
@@ -319,7 +493,7 @@ pub async fn read_sales_for_asset(
                 *
             from
                 sale
-            where collection_slug = $1 and token_id = $2
+            where collection_slug = $1 and token_id = $2 and valid_sale(flag, collection_slug)
         "#,
         collection_slug,
         token_id,
@@ -329,56 +503,128 @@ pub async fn read_sales_for_asset(
     .map_err(|e| e.into())
 }
 
-pub async fn read_30d_avg_price_collection_at_ts(
+/// Most recent sale of every token in `token_ids` that has one
+pub async fn read_latest_sale_for_tokens(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_ids: &[i32],
+) -> Result<Vec<SaleEvent>> {
+    sqlx::query_as!(
+        SaleEvent,
+        r#"
+            select
+                distinct on (token_id) *
+            from
+                sale
+            where collection_slug = $1 and token_id = any($2) and valid_sale(flag, collection_slug)
+            order by token_id, timestamp desc
+        "#,
+        collection_slug,
+        token_ids,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Sales of the tokens in `token_ids` after `start` and before `end`, most recent first
+pub async fn read_sales_for_tokens_between_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_ids: &[i32],
+    start: &NaiveDateTime,
+    end: &NaiveDateTime,
+) -> Result<Vec<SaleEvent>> {
+    sqlx::query_as!(
+        SaleEvent,
+        r#"
+            select
+                *
+            from
+                sale
+            where collection_slug = $1 and token_id = any($2) and timestamp > $3 and timestamp < $4
+            and valid_sale(flag, collection_slug)
+            order by timestamp desc
+        "#,
+        collection_slug,
+        token_ids,
+        start.timestamp() as i32,
+        end.timestamp() as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn read_sales_for_collection_after_ts(
     conn: &mut PgConnection,
     collection_slug: &str,
     timestamp: &NaiveDateTime,
-) -> Result<Option<f64>> {
-    let avg = sqlx::query_scalar!(
+) -> Result<Vec<SaleEvent>> {
+    sqlx::query_as!(
+        SaleEvent,
         r#"
             select
-                avg(price) 
+                *
             from
                 sale
-            where collection_slug = $1 and timestamp < $2 and timestamp > $3 and price is not null
+            where collection_slug = $1 and timestamp > $2 and valid_sale(flag, collection_slug)
+            order by timestamp desc
         "#,
         collection_slug,
         timestamp.timestamp() as i32,
-        (*timestamp - Duration::days(60)).timestamp() as i32,
     )
-    .fetch_one(&mut *conn)
-    .await?;
-    Ok(avg)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
 }
 
-pub async fn read_30d_avg_price_trait_at_ts(
+/// Includes flagged sales, only meant for classifying them
+pub async fn read_all_sales_for_collection(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Vec<SaleEvent>> {
+    sqlx::query_as!(
+        SaleEvent,
+        r#"
+            select
+                *
+            from
+                sale
+            where collection_slug = $1
+            order by timestamp asc
+        "#,
+        collection_slug,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Average sale price over the `days` before `timestamp`
+pub async fn read_avg_price_collection_at_ts(
     conn: &mut PgConnection,
     collection_slug: &str,
-    trait_id: &str,
     timestamp: &NaiveDateTime,
+    days: i64,
 ) -> Result<Option<f64>> {
-    sqlx::query_scalar!(
+    let avg = sqlx::query_scalar!(
         r#"
             select
-                avg(price)
+                avg(price) 
             from
                 sale
-            where collection_slug = $1 and timestamp < $2 and timestamp > $3 and token_id = any(
-                select
-                    token_id
-                from
-                    asset a     
-                where a.collection_slug = $1 and  $4 = any(a.traits) 
-            ) and price is not null
+            where collection_slug = $1 and timestamp < $2 and price is not null
+            and timestamp > $2 - 86400 * $3
+            and valid_sale(flag, collection_slug)
         "#,
         collection_slug,
         timestamp.timestamp() as i32,
-        (*timestamp - Duration::days(60)).timestamp() as i32,
-        trait_id,
+        days as i32,
     )
     .fetch_one(&mut *conn)
-    .await
-    .map_err(|e| e.into())
+    .await?;
+    Ok(avg)
 }
 
 // ============ Listings ============
@@ -433,12 +679,17 @@ pub async fn read_listed_for_collection_at_ts(
     sqlx::query_as!(
         Listing,
         r#"
-        select 
-            distinct on (token_id) *
-        from 
-            listing
-        where collection_slug = $1 and timestamp < $2 and price is not null
-        order by token_id, timestamp desc
+        select
+            *
+        from (
+            select
+                distinct on (token_id) *
+            from
+                listing
+            where collection_slug = $1 and timestamp < $2
+            order by token_id, timestamp desc
+        ) as l
+        where price is not null
 
         "#,
         collection_slug,
@@ -449,11 +700,12 @@ pub async fn read_listed_for_collection_at_ts(
     .map_err(|e| e.into())
 }
 
-pub async fn read_listing_update_type_count_after_ts(
+pub async fn read_listing_update_type_count_between_ts(
     conn: &mut PgConnection,
     collection_slug: &str,
     update_type: &str,
-    timestamp: &NaiveDateTime,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
 ) -> Result<Option<i64>> {
     sqlx::query_scalar!(
         r#"
@@ -461,11 +713,12 @@ pub async fn read_listing_update_type_count_after_ts(
                 count(distinct(token_id))
             from
                 listing
-            where collection_slug = $1 and update_type = $2 and timestamp > $3
+            where collection_slug = $1 and update_type = $2 and timestamp > $3 and timestamp < $4
         "#,
         collection_slug,
         update_type,
-        timestamp.timestamp() as i32,
+        from.timestamp() as i32,
+        to.timestamp() as i32,
     )
     .fetch_one(&mut *conn)
     .await
@@ -483,14 +736,19 @@ pub async fn read_trait_listings_at_ts(
         r#"
             select
                 *
-            from
-            listing  
-            where collection_slug = $1 and token_id = any( select
-                token_id
+            from (
+                select
+                    distinct on (token_id) *
                 from
-                    asset a     
-                where a.collection_slug = $1 and  $2 = any(a.traits)) and timestamp < $3
-            order by timestamp desc
+                    listing
+                where collection_slug = $1 and token_id = any( select
+                    token_id
+                    from
+                        asset a
+                    where a.collection_slug = $1 and  $2 = any(a.traits)) and timestamp < $3
+                order by token_id, timestamp desc
+            ) as l
+            where price is not null
         "#,
         collection_slug,
         trait_id,
@@ -501,11 +759,12 @@ pub async fn read_trait_listings_at_ts(
     .map_err(|e| e.into())
 }
 
-pub async fn read_listings_token_after_ts(
+pub async fn read_listings_token_between_ts(
     conn: &mut PgConnection,
     collection_slug: &str,
     token_id: i32,
-    timestamp: &NaiveDateTime,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
 ) -> Result<Vec<Listing>> {
     sqlx::query_as!(
         Listing,
@@ -514,16 +773,516 @@ pub async fn read_listings_token_after_ts(
                 *
             from
                 listing
-            where collection_slug = $1 and token_id = $2 and timestamp > $3 and (
+            where collection_slug = $1 and token_id = $2 and timestamp > $3 and timestamp < $4 and (
                 update_type = 'created' or  (update_type = 'sell_order' and price is not null)
             )
             order by token_id, timestamp
         "#,
         collection_slug,
         token_id,
-        timestamp.timestamp() as i32,
+        from.timestamp() as i32,
+        to.timestamp() as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn read_all_listings_for_collection(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Vec<Listing>> {
+    sqlx::query_as!(
+        Listing,
+        r#"
+            select
+                *
+            from
+                listing
+            where collection_slug = $1
+            order by timestamp asc
+        "#,
+        collection_slug,
     )
     .fetch_all(&mut *conn)
     .await
     .map_err(|e| e.into())
 }
+
+/// At or after the timestamp, oldest first
+pub async fn read_listings_for_collection_after_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    timestamp: &NaiveDateTime,
+) -> Result<Vec<Listing>> {
+    sqlx::query_as!(
+        Listing,
+        r#"
+            select
+                *
+            from
+                listing
+            where collection_slug = $1 and timestamp >= $2
+            order by timestamp asc
+        "#,
+        collection_slug,
+        timestamp.timestamp() as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+// ============ Bids ============
+pub async fn read_latest_bid_for_collection(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Option<i32>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                max(timestamp)
+            from
+                bid
+            where collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Bids open at `timestamp`. Only the latest update of every bidder counts, bids without an
+/// expiry are open for `max_age`
+pub async fn read_open_bids_at_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    timestamp: &NaiveDateTime,
+    max_age: &Duration,
+) -> Result<Vec<Bid>> {
+    sqlx::query_as!(
+        Bid,
+        r#"
+        select
+            *
+        from (
+            select
+                distinct on (token_id, trait_id, bidder) *
+            from
+                bid
+            where collection_slug = $1 and timestamp < $2
+            order by token_id, trait_id, bidder, timestamp desc
+        ) as b
+        where update_type <> 'bid_withdrawn' and price is not null
+            and coalesce(expires_at, timestamp + $3) > $2
+        "#,
+        collection_slug,
+        timestamp.timestamp() as i32,
+        max_age.num_seconds() as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+// ============ Backtest ============
+pub async fn read_backtest_results(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Vec<BacktestResult>> {
+    sqlx::query_as!(
+        BacktestResult,
+        r#"
+            select
+                *
+            from
+                backtest_result
+            where collection_slug = $1
+            order by mape asc
+        "#,
+        collection_slug,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+// ============ Hedonic Model ============
+/// Latest model fitted at or before the timestamp, it only knows sales before it
+pub async fn read_hedonic_model_at_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    timestamp: &NaiveDateTime,
+) -> Result<Option<HedonicModel>> {
+    sqlx::query_as!(
+        HedonicModel,
+        r#"
+            select
+                *
+            from
+                hedonic_model
+            where collection_slug = $1 and timestamp <= $2
+            order by timestamp desc
+            limit 1
+        "#,
+        collection_slug,
+        timestamp.timestamp() as i32,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn read_trait_premiums(
+    conn: &mut PgConnection,
+    model: &HedonicModel,
+    trait_ids: &[String],
+) -> Result<Vec<TraitPremium>> {
+    sqlx::query_as!(
+        TraitPremium,
+        r#"
+            select
+                *
+            from
+                trait_premium
+            where collection_slug = $1 and timestamp = $2 and trait_id = any($3)
+        "#,
+        model.collection_slug,
+        model.timestamp,
+        trait_ids,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+// ============ FX ============
+/// Rate closest to the timestamp, preferring the last one before it
+pub async fn read_fx_rate_at_ts(
+    conn: &mut PgConnection,
+    timestamp: &NaiveDateTime,
+) -> Result<Option<FxRate>> {
+    sqlx::query_as!(
+        FxRate,
+        r#"
+            select
+                *
+            from
+                fx_rate
+            order by timestamp > $1, abs(timestamp - $1)
+            limit 1
+        "#,
+        timestamp.timestamp() as i32,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Oldest first
+pub async fn read_fx_rates(conn: &mut PgConnection) -> Result<Vec<FxRate>> {
+    sqlx::query_as!(
+        FxRate,
+        r#"
+            select
+                *
+            from
+                fx_rate
+            order by timestamp asc
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn read_latest_fx_rate(conn: &mut PgConnection) -> Result<Option<FxRate>> {
+    sqlx::query_as!(
+        FxRate,
+        r#"
+            select
+                *
+            from
+                fx_rate
+            order by timestamp desc
+            limit 1
+        "#,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+// ============ Price Snapshot ============
+pub async fn read_price_snapshots_for_token(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_id: i32,
+    timestamp: &NaiveDateTime,
+) -> Result<Vec<PriceSnapshot>> {
+    sqlx::query_as!(
+        PriceSnapshot,
+        r#"
+            select
+                collection_slug,
+                token_id,
+                timestamp,
+                strategies as "strategies: sqlx::types::Json<HashMap<String, Option<f64>>>",
+                min_price,
+                avg_price,
+                max_price,
+                confidence
+            from
+                price_snapshot
+            where collection_slug = $1 and token_id = $2 and timestamp > $3
+            order by timestamp asc
+        "#,
+        collection_slug,
+        token_id,
+        timestamp.timestamp() as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Latest `avg_price` of every token in `token_ids` at or before the timestamp at the same
+/// position in `timestamps`, None where no snapshot was taken yet
+pub async fn read_snapshot_avg_prices_at_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_ids: &[i32],
+    timestamps: &[i32],
+) -> Result<Vec<Option<f64>>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                (
+                    select
+                        s.avg_price
+                    from
+                        price_snapshot s
+                    where s.collection_slug = $1 and s.token_id = e.token_id and s.timestamp <= e.timestamp
+                    order by s.timestamp desc
+                    limit 1
+                )
+            from
+                unnest($2::int[], $3::int[]) with ordinality as e(token_id, timestamp, position)
+            order by e.position
+        "#,
+        collection_slug,
+        token_ids,
+        timestamps,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+pub async fn read_latest_price_snapshot_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Option<i32>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                max(timestamp)
+            from
+                price_snapshot
+            where collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+// ============ Trait Index ============
+pub async fn read_trait_index(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    trait_id: &str,
+    timestamp: &NaiveDateTime,
+) -> Result<Vec<TraitIndex>> {
+    sqlx::query_as!(
+        TraitIndex,
+        r#"
+            select
+                *
+            from
+                trait_index
+            where collection_slug = $1 and trait_id = $2 and timestamp > $3
+            order by timestamp asc
+        "#,
+        collection_slug,
+        trait_id,
+        timestamp.timestamp() as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Start of the first day in the trait index of the collection
+pub async fn read_trait_index_start(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Option<i32>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                min(timestamp)
+            from
+                trait_index
+            where collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Time of the first valid sale of a token with stored traits, the first one the trait index
+/// has a row for
+pub async fn read_first_trait_sale_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+) -> Result<Option<i32>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                min(s.timestamp)
+            from
+                sale s
+            join asset a on a.collection_slug = s.collection_slug and a.token_id = s.token_id
+            where s.collection_slug = $1 and s.price is not null
+            and cardinality(a.traits) > 0
+            and valid_sale(s.flag, s.collection_slug)
+        "#,
+        collection_slug,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Average sale price of the trait over the `days` before `timestamp`, only complete days count
+pub async fn read_avg_price_trait_index_at_ts(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    trait_id: &str,
+    timestamp: &NaiveDateTime,
+    days: i64,
+) -> Result<Option<f64>> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                sum(volume) / nullif(sum(nr_sales), 0)
+            from
+                trait_index
+            where collection_slug = $1 and trait_id = $2 and timestamp + 86400 <= $3
+            and timestamp > $3 - 86400 * ($4 + 1)
+        "#,
+        collection_slug,
+        trait_id,
+        timestamp.timestamp() as i32,
+        days as i32,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+// ============ Rarity Score ============
+pub async fn read_rarity_score(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    token_id: i32,
+) -> Result<Option<RarityScore>> {
+    sqlx::query_as!(
+        RarityScore,
+        r#"
+            select
+                *
+            from
+                rarity_score
+            where collection_slug = $1 and token_id = $2
+        "#,
+        collection_slug,
+        token_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Scores of the collection, rarest first under `model`
+pub async fn read_rarity_ranking(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    model: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<RarityScore>> {
+    sqlx::query_as!(
+        RarityScore,
+        r#"
+            select
+                *
+            from
+                rarity_score
+            where collection_slug = $1
+            order by
+                case
+                    when $2 = 'statistical_rarity' then statistical_rarity_rank
+                    when $2 = 'information_content' then information_content_rank
+                    else rarity_score_rank
+                end,
+                token_id
+            limit $3 offset $4
+        "#,
+        collection_slug,
+        model,
+        limit,
+        offset,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Number of stored assets strictly rarer than the given scores under each model
+pub async fn read_nr_rarer_assets(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    statistical_rarity: f64,
+    rarity_score: f64,
+    information_content: f64,
+) -> Result<(i64, i64, i64)> {
+    let row = sqlx::query!(
+        r#"
+            select
+                count(*) filter (where statistical_rarity < $2) as "statistical_rarity!",
+                count(*) filter (where rarity_score > $3) as "rarity_score!",
+                count(*) filter (where information_content > $4) as "information_content!"
+            from
+                rarity_score
+            where collection_slug = $1
+        "#,
+        collection_slug,
+        statistical_rarity,
+        rarity_score,
+        information_content,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok((
+        row.statistical_rarity,
+        row.rarity_score,
+        row.information_content,
+    ))
+}
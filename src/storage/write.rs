@@ -1,7 +1,9 @@
-use crate::opensea::types::{Collection, Event};
+use crate::opensea::types::{AssetContract, Collection, Event};
 use anyhow::Result;
+use chrono::NaiveDateTime;
 use sqlx::postgres::PgQueryResult;
 use sqlx::{Acquire, PgConnection};
+use std::collections::HashMap;
 
 // ============ ASSET ============
 pub async fn write_asset(conn: &mut PgConnection, asset: &super::Asset) -> Result<PgQueryResult> {
@@ -14,16 +16,10 @@ pub async fn write_asset(conn: &mut PgConnection, asset: &super::Asset) -> Resul
         image_url,
         owner,
         traits,
-        unique_traits,
-        traits_3_combination_overlap,
-        traits_4_combination_overlap,
-        traits_5_combination_overlap,
-        traits_3_combination_overlap_ids,
-        traits_4_combination_overlap_ids,
-        traits_5_combination_overlap_ids
+        unique_traits
        )
        values
-           ($1, $2, $3, $4,$5, $6, $7, $8, $9, $10, $11, $12, $13);
+           ($1, $2, $3, $4,$5, $6, $7);
        "#,
         asset.name,
         asset.collection_slug.to_lowercase(),
@@ -32,50 +28,55 @@ pub async fn write_asset(conn: &mut PgConnection, asset: &super::Asset) -> Resul
         asset.owner,
         &asset.traits,
         asset.unique_traits,
-        asset.traits_3_combination_overlap,
-        asset.traits_4_combination_overlap,
-        asset.traits_5_combination_overlap,
-        &asset.traits_3_combination_overlap_ids,
-        &asset.traits_4_combination_overlap_ids,
-        &asset.traits_5_combination_overlap_ids,
     )
     .execute(conn)
     .await
     .map_err(|e| e.into())
 }
 
-pub async fn update_asset_overlap(
+/// Replaces all overlaps of the collection, sizes no longer configured are removed
+pub async fn write_asset_overlaps(
     conn: &mut PgConnection,
-    asset: &super::Asset,
-) -> Result<PgQueryResult> {
+    collection_slug: &str,
+    overlaps: &[super::AssetOverlap],
+) -> Result<()> {
+    let mut txn = conn.begin().await?;
     sqlx::query!(
         r#"
-       update asset
-       set
-        traits_3_combination_overlap = $3,
-        traits_4_combination_overlap = $4,
-        traits_5_combination_overlap = $5,
-        traits_3_combination_overlap_ids = $6,
-        traits_4_combination_overlap_ids = $7,
-        traits_5_combination_overlap_ids = $8
-    where collection_slug = $1 and token_id = $2
-       
-       "#,
-        asset.collection_slug.to_lowercase(),
-        asset.token_id as i32,
-        asset.traits_3_combination_overlap,
-        asset.traits_4_combination_overlap,
-        asset.traits_5_combination_overlap,
-        &asset.traits_3_combination_overlap_ids,
-        &asset.traits_4_combination_overlap_ids,
-        &asset.traits_5_combination_overlap_ids,
+        delete from asset_overlap where collection_slug = $1
+        "#,
+        collection_slug,
     )
-    .execute(conn)
-    .await
-    .map_err(|e| e.into())
+    .execute(&mut txn)
+    .await?;
+
+    for o in overlaps {
+        sqlx::query!(
+            r#"
+        insert into asset_overlap(
+            collection_slug,
+            token_id,
+            combination_size,
+            nr_overlapping,
+            overlapping_ids
+        )
+        values
+            ($1, $2, $3, $4, $5)
+        "#,
+            o.collection_slug,
+            o.token_id,
+            o.combination_size,
+            o.nr_overlapping,
+            &o.overlapping_ids,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+    txn.commit().await.map_err(|e| e.into())
 }
 
 // ============ COLLECTION ============
+#[allow(clippy::too_many_arguments)]
 pub async fn write_collection(
     conn: &mut PgConnection,
     collection: &Collection,
@@ -83,6 +84,10 @@ pub async fn write_collection(
     multiplier: f64,
     ignored_trait_types_rarity: Vec<String>,
     ignored_trait_types_overlap: Vec<String>,
+    overlap_sizes: Vec<i32>,
+    pricing_strategies: Vec<String>,
+    include_flagged_sales: bool,
+    analysis_window_days: Option<i32>,
     address: Option<String>,
 ) -> Result<PgQueryResult> {
     sqlx::query!(
@@ -93,6 +98,8 @@ pub async fn write_collection(
             address,
             ignored_trait_types_rarity,
             ignored_trait_types_overlap,
+            pricing_strategies,
+            include_flagged_sales,
             total_supply,
             rarity_cutoff,
             floor_price,
@@ -103,10 +110,15 @@ pub async fn write_collection(
             daily_avg_price,
             weekly_avg_price,
             monthly_avg_price,
-            nr_owners
+            nr_owners,
+            seller_fee_basis_points,
+            opensea_seller_fee_basis_points,
+            dev_seller_fee_basis_points,
+            overlap_sizes,
+            analysis_window_days
        )
        values
-           ($1, $2, $3, $4, $5,$6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16);
+           ($1, $2, $3, $4, $5,$6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23);
        "#,
         collection.slug.to_lowercase(),
         collection.name.clone().unwrap_or_default(),
@@ -117,6 +129,8 @@ pub async fn write_collection(
         },
         &ignored_trait_types_rarity,
         &ignored_trait_types_overlap,
+        &pricing_strategies,
+        include_flagged_sales,
         collection.stats.total_supply as i32,
         (avg_trait_rarity * multiplier) / collection.stats.total_supply,
         collection.stats.floor_price.unwrap_or_default(),
@@ -128,6 +142,23 @@ pub async fn write_collection(
         collection.stats.weekly_avg_price,
         collection.stats.monthly_avg_price,
         collection.stats.nr_owners,
+        collection
+            .primary_asset_contracts
+            .first()
+            .map(|c| c.seller_fee_basis_points as i32)
+            .unwrap_or_default(),
+        collection
+            .primary_asset_contracts
+            .first()
+            .map(|c| c.opensea_seller_fee_basis_points as i32)
+            .unwrap_or_default(),
+        collection
+            .primary_asset_contracts
+            .first()
+            .map(|c| c.dev_seller_fee_basis_points as i32)
+            .unwrap_or_default(),
+        &overlap_sizes,
+        analysis_window_days,
     )
     .execute(conn)
     .await
@@ -154,12 +185,62 @@ pub async fn update_collection_floor(
     .map_err(|e| e.into())
 }
 
+pub async fn update_collection_owners_synced_at(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    synced_at: &NaiveDateTime,
+) -> Result<PgQueryResult> {
+    sqlx::query!(
+        r#"
+        update collection
+            set
+            owners_synced_at = $1
+        where slug = $2
+       "#,
+        synced_at.timestamp() as i32,
+        collection_slug
+    )
+    .execute(conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+/// Fees of the collection's primary contract, nothing is updated without a contract
+pub async fn update_collection_fees(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    contract: &AssetContract,
+) -> Result<PgQueryResult> {
+    sqlx::query!(
+        r#"
+        update collection
+            set
+            seller_fee_basis_points = $1,
+            opensea_seller_fee_basis_points = $2,
+            dev_seller_fee_basis_points = $3
+        where slug = $4
+       "#,
+        contract.seller_fee_basis_points as i32,
+        contract.opensea_seller_fee_basis_points as i32,
+        contract.dev_seller_fee_basis_points as i32,
+        collection_slug
+    )
+    .execute(conn)
+    .await
+    .map_err(|e| e.into())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn update_collection_info(
     conn: &mut PgConnection,
     collection_slug: &str,
     total_supply: f64,
     ignored_trait_types_rarity: Vec<String>,
     ignored_trait_types_overlap: Vec<String>,
+    overlap_sizes: Vec<i32>,
+    pricing_strategies: Vec<String>,
+    include_flagged_sales: bool,
+    analysis_window_days: Option<i32>,
     rarity_cutoff: f64,
 ) -> Result<PgQueryResult> {
     sqlx::query!(
@@ -168,6 +249,10 @@ pub async fn update_collection_info(
             set
             ignored_trait_types_rarity = $1,
             ignored_trait_types_overlap = $2,
+            pricing_strategies = $6,
+            include_flagged_sales = $7,
+            overlap_sizes = $8,
+            analysis_window_days = $9,
             rarity_cutoff = $3,
             total_supply = $4
         where slug= $5
@@ -176,7 +261,11 @@ pub async fn update_collection_info(
         &ignored_trait_types_overlap,
         rarity_cutoff,
         total_supply as i32,
-        collection_slug
+        collection_slug,
+        &pricing_strategies,
+        include_flagged_sales,
+        &overlap_sizes,
+        analysis_window_days,
     )
     .execute(conn)
     .await
@@ -289,27 +378,137 @@ pub async fn write_sale(
         // Bundles dont have an asset, we ignore bundle sales
         return Ok(());
     };
+    let payment_token = match &sale.payment_token {
+        Some(p) => p,
+        // Without a payment token the price can't be converted to ETH
+        None => return Ok(()),
+    };
+    let amount = sale.total_price.clone().unwrap().parse::<f64>().unwrap();
     sqlx::query!(
         r#"
        insert into sale(
         collection_slug,
         token_id,
         price,
-        timestamp
+        timestamp,
+        buyer,
+        seller,
+        payment_token,
+        payment_amount,
+        payment_decimals
        )
        values
-           ($1, $2, $3, $4);
+           ($1, $2, $3, $4, $5, $6, $7, $8, $9);
        "#,
         collection_slug.to_lowercase(),
         token_id,
-        sale.total_price.clone().unwrap().parse::<f64>().unwrap(),
+        payment_token.eth_wei_amount(amount),
         sale.created_date.timestamp() as i32,
+        sale.winner_account
+            .as_ref()
+            .map(|a| a.address.to_lowercase()),
+        sale.seller.as_ref().map(|a| a.address.to_lowercase()),
+        payment_token.symbol,
+        amount,
+        payment_token.decimals as i32,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Offers without an asset are stored as collection bids, withdrawals are stored without a price
+pub async fn write_bid(
+    conn: &mut PgConnection,
+    bid: &Event,
+    collection_slug: &str,
+    update_type: &str,
+) -> Result<()> {
+    let bidder = match &bid.from_account {
+        Some(a) => a.address.to_lowercase(),
+        None => return Ok(()),
+    };
+    let price = match (&bid.payment_token, &bid.bid_amount) {
+        (Some(p), Some(a)) => Some(p.eth_wei_amount(a.parse::<f64>()?)),
+        _ => None,
+    };
+    let expires_at = bid
+        .duration
+        .as_ref()
+        .and_then(|d| d.parse::<i64>().ok())
+        .map(|d| (bid.created_date.timestamp() + d) as i32);
+    let token_id = bid.asset.as_ref().map(|a| a.token_id);
+    let trait_id = match token_id {
+        Some(_) => None,
+        None => bid.criteria.as_ref().and_then(|c| c.trait_id()),
+    };
+
+    sqlx::query!(
+        r#"
+       insert into bid(
+        collection_slug,
+        update_type,
+        token_id,
+        trait_id,
+        bidder,
+        price,
+        timestamp,
+        expires_at
+       )
+       values
+           ($1, $2, $3, $4, $5, $6, $7, $8)
+       on conflict do nothing;
+       "#,
+        collection_slug.to_lowercase(),
+        update_type,
+        token_id,
+        trait_id,
+        bidder,
+        price,
+        bid.created_date.timestamp() as i32,
+        expires_at,
     )
     .execute(conn)
     .await?;
     Ok(())
 }
 
+/// Replaces the flags of all sales of a collection, keys are (token_id, timestamp)
+pub async fn write_sale_flags(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    flags: &HashMap<(i32, i32), &str>,
+) -> Result<()> {
+    let mut txn = conn.begin().await?;
+    sqlx::query!(
+        r#"
+        update sale
+            set flag = null
+        where collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .execute(&mut txn)
+    .await?;
+
+    for ((token_id, timestamp), flag) in flags {
+        sqlx::query!(
+            r#"
+            update sale
+                set flag = $4
+            where collection_slug = $1 and token_id = $2 and timestamp = $3
+            "#,
+            collection_slug,
+            token_id,
+            timestamp,
+            flag,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+    txn.commit().await.map_err(|e| e.into())
+}
+
 pub async fn write_transfer(
     conn: &mut PgConnection,
     token_id: i32,
@@ -362,3 +561,278 @@ pub async fn write_listing(
     .await?;
     Ok(())
 }
+
+// ============ BACKTEST ============
+pub async fn write_backtest_results(
+    conn: &mut PgConnection,
+    results: &[super::BacktestResult],
+) -> Result<()> {
+    let mut txn = conn.begin().await?;
+    for r in results {
+        sqlx::query!(
+            r#"
+        insert into backtest_result(
+            collection_slug,
+            strategy,
+            nr_sales,
+            nr_priced,
+            mae,
+            mape,
+            hit_rate,
+            timestamp
+        )
+        values
+            ($1, $2, $3, $4, $5, $6, $7, $8)
+        on conflict (collection_slug, strategy) do update
+            set
+            nr_sales = excluded.nr_sales,
+            nr_priced = excluded.nr_priced,
+            mae = excluded.mae,
+            mape = excluded.mape,
+            hit_rate = excluded.hit_rate,
+            timestamp = excluded.timestamp
+        "#,
+            r.collection_slug,
+            r.strategy,
+            r.nr_sales,
+            r.nr_priced,
+            r.mae,
+            r.mape,
+            r.hit_rate,
+            r.timestamp,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+    txn.commit().await.map_err(|e| e.into())
+}
+
+// ============ HEDONIC MODEL ============
+pub async fn write_hedonic_model(
+    conn: &mut PgConnection,
+    model: &super::HedonicModel,
+    premiums: Vec<super::TraitPremium>,
+) -> Result<()> {
+    let mut txn = conn.begin().await?;
+
+    sqlx::query!(
+        r#"
+        insert into hedonic_model(
+            collection_slug,
+            intercept,
+            market_coefficient,
+            r_squared,
+            nr_sales,
+            timestamp
+        )
+        values
+            ($1, $2, $3, $4, $5, $6)
+        on conflict (collection_slug, timestamp) do update
+            set
+            intercept = excluded.intercept,
+            market_coefficient = excluded.market_coefficient,
+            r_squared = excluded.r_squared,
+            nr_sales = excluded.nr_sales
+        "#,
+        model.collection_slug,
+        model.intercept,
+        model.market_coefficient,
+        model.r_squared,
+        model.nr_sales,
+        model.timestamp,
+    )
+    .execute(&mut txn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        delete from trait_premium where collection_slug = $1 and timestamp = $2
+        "#,
+        model.collection_slug,
+        model.timestamp,
+    )
+    .execute(&mut txn)
+    .await?;
+
+    for p in premiums {
+        sqlx::query!(
+            r#"
+        insert into trait_premium(
+            collection_slug,
+            trait_id,
+            premium,
+            timestamp
+        )
+        values
+            ($1, $2, $3, $4)
+        "#,
+            p.collection_slug,
+            p.trait_id,
+            p.premium,
+            p.timestamp,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+
+    txn.commit().await.map_err(|e| e.into())
+}
+
+// ============ FX ============
+pub async fn write_fx_rates(conn: &mut PgConnection, rates: &[super::FxRate]) -> Result<()> {
+    let mut txn = conn.begin().await?;
+    for r in rates {
+        sqlx::query!(
+            r#"
+        insert into fx_rate(
+            timestamp,
+            eth_usd
+        )
+        values
+            ($1, $2)
+        on conflict (timestamp) do update set
+            eth_usd = excluded.eth_usd
+        "#,
+            r.timestamp,
+            r.eth_usd,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+    txn.commit().await.map_err(|e| e.into())
+}
+
+// ============ PRICE SNAPSHOT ============
+/// Replaces the index of the collection from `timestamp` on
+pub async fn write_trait_index(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    timestamp: i32,
+    index: &[super::TraitIndex],
+) -> Result<()> {
+    let mut txn = conn.begin().await?;
+    sqlx::query!(
+        r#"
+        delete from trait_index where collection_slug = $1 and timestamp >= $2
+        "#,
+        collection_slug,
+        timestamp,
+    )
+    .execute(&mut txn)
+    .await?;
+
+    for i in index {
+        sqlx::query!(
+            r#"
+        insert into trait_index(
+            collection_slug,
+            trait_id,
+            timestamp,
+            floor_price,
+            median_price,
+            avg_price,
+            volume,
+            nr_sales
+        )
+        values
+            ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+            i.collection_slug,
+            i.trait_id,
+            i.timestamp,
+            i.floor_price,
+            i.median_price,
+            i.avg_price,
+            i.volume,
+            i.nr_sales,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+    txn.commit().await.map_err(|e| e.into())
+}
+
+pub async fn write_price_snapshots(
+    conn: &mut PgConnection,
+    snapshots: &[super::PriceSnapshot],
+) -> Result<()> {
+    let mut txn = conn.begin().await?;
+    for s in snapshots {
+        sqlx::query!(
+            r#"
+        insert into price_snapshot(
+            collection_slug,
+            token_id,
+            timestamp,
+            strategies,
+            min_price,
+            avg_price,
+            max_price,
+            confidence
+        )
+        values
+            ($1, $2, $3, $4, $5, $6, $7, $8)
+        on conflict do nothing
+        "#,
+            s.collection_slug,
+            s.token_id,
+            s.timestamp,
+            s.strategies as _,
+            s.min_price,
+            s.avg_price,
+            s.max_price,
+            s.confidence,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+    txn.commit().await.map_err(|e| e.into())
+}
+
+// ============ RARITY SCORE ============
+/// Replaces the scores of the collection
+pub async fn write_rarity_scores(
+    conn: &mut PgConnection,
+    collection_slug: &str,
+    scores: &[super::RarityScore],
+) -> Result<()> {
+    let mut txn = conn.begin().await?;
+    sqlx::query!(
+        r#"
+        delete from rarity_score where collection_slug = $1
+        "#,
+        collection_slug,
+    )
+    .execute(&mut txn)
+    .await?;
+
+    for s in scores {
+        sqlx::query!(
+            r#"
+        insert into rarity_score(
+            collection_slug,
+            token_id,
+            statistical_rarity,
+            rarity_score,
+            information_content,
+            statistical_rarity_rank,
+            rarity_score_rank,
+            information_content_rank
+        )
+        values
+            ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+            s.collection_slug,
+            s.token_id,
+            s.statistical_rarity,
+            s.rarity_score,
+            s.information_content,
+            s.statistical_rarity_rank,
+            s.rarity_score_rank,
+            s.information_content_rank,
+        )
+        .execute(&mut txn)
+        .await?;
+    }
+    txn.commit().await.map_err(|e| e.into())
+}
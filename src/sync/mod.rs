@@ -1 +1,2 @@
+pub mod snapshots;
 pub mod sync_events;
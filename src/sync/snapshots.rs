@@ -0,0 +1,72 @@
+use crate::profiles::price_profile::PriceProfile;
+use crate::storage::read::{
+    read_collection, read_latest_price_snapshot_ts, read_token_ids_for_collection,
+};
+use crate::storage::write::write_price_snapshots;
+use crate::storage::{Collection, PriceSnapshot};
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures::StreamExt;
+use sqlx::PgPool;
+
+/// Minimum time between two snapshots of the same collection
+static SNAPSHOT_INTERVAL_HOURS: i64 = 24;
+
+/// Prices every asset of the collection and stores the result, returns the number of priced
+/// assets. Skipped when the last snapshot is more recent than `SNAPSHOT_INTERVAL_HOURS`
+pub async fn snapshot_collection(pool: PgPool, collection_slug: &str) -> Result<usize> {
+    let mut conn = pool.acquire().await?;
+    let now = Utc::now().naive_utc();
+
+    if let Some(latest) = read_latest_price_snapshot_ts(&mut conn, collection_slug).await? {
+        if now.timestamp() - (latest as i64)
+            < Duration::hours(SNAPSHOT_INTERVAL_HOURS).num_seconds()
+        {
+            return Ok(0);
+        }
+    }
+
+    let collection = read_collection(&mut conn, collection_slug).await?;
+    let token_ids = read_token_ids_for_collection(&mut conn, collection_slug).await?;
+
+    let mut stream = futures::stream::iter(token_ids)
+        .map(|token_id| snapshot_token(pool.clone(), &collection, token_id, &now))
+        .buffer_unordered(6);
+
+    let mut snapshots = vec![];
+    while let Some(result) = stream.next().await {
+        match result {
+            Ok(Some(s)) => snapshots.push(s),
+            Ok(None) => (),
+            Err(e) => log::info!("Error pricing token: {}", e),
+        }
+    }
+
+    write_price_snapshots(&mut conn, &snapshots).await?;
+
+    Ok(snapshots.len())
+}
+
+async fn snapshot_token(
+    pool: PgPool,
+    collection: &Collection,
+    token_id: i32,
+    ts: &NaiveDateTime,
+) -> Result<Option<PriceSnapshot>> {
+    let mut conn = pool.acquire().await?;
+    let profile = match PriceProfile::make_for_token(&mut conn, collection, token_id, None).await? {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+
+    Ok(Some(PriceSnapshot {
+        collection_slug: collection.slug.clone(),
+        token_id,
+        timestamp: ts.timestamp() as i32,
+        strategies: sqlx::types::Json(profile.strategies),
+        min_price: profile.min_price,
+        avg_price: profile.avg_price,
+        max_price: profile.max_price,
+        confidence: profile.confidence.score,
+    }))
+}
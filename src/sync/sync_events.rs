@@ -1,13 +1,43 @@
+use crate::analyzers::hedonic::refit_hedonic_model;
+use crate::analyzers::trait_index::{build_trait_index, get_trait_index_since};
+use crate::analyzers::wash_trades::classify_sales;
 use crate::opensea::{fetchers::*, os_client::OpenseaAPIClient};
-use crate::storage::{establish_connection, read::*, write::*, CollectionSmall};
+use crate::storage::{establish_connection, read::*, write::*, CollectionSmall, FxRate};
+use crate::sync::snapshots::snapshot_collection;
 use anyhow::Result;
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime, Utc};
 use governor::{Quota, RateLimiter};
 use sqlx::PgConnection;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Jobs the sync loop runs in the background so a slow one doesn't hold up the next sync
+#[derive(Clone, Default)]
+struct BackgroundJobs(Arc<Mutex<HashSet<String>>>);
+
+impl BackgroundJobs {
+    /// Runs `job` in the background unless the previous job under `key` is still running
+    fn spawn<F>(&self, key: String, job: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        if !self.0.lock().unwrap().insert(key.clone()) {
+            log::info!("Skipping {}, still running", key);
+            return;
+        }
+        let running = self.0.clone();
+        tokio::spawn(async move {
+            job.await;
+            running.lock().unwrap().remove(&key);
+        });
+    }
+}
 
 pub async fn sync_events_loop() -> Result<()> {
     let rate_limiter =
         RateLimiter::direct(Quota::with_period(std::time::Duration::from_secs(600u64)).unwrap());
+    let jobs = BackgroundJobs::default();
     loop {
         rate_limiter.until_ready().await;
         let pool = establish_connection().await;
@@ -16,9 +46,45 @@ pub async fn sync_events_loop() -> Result<()> {
         let collections = read_all_collections(&mut conn).await?;
 
         for collection in collections {
-            sync_collection(&mut conn, &collection, None, None)
-                .await
-                .unwrap_or_default();
+            let synced = sync_collection(&mut conn, &collection, None, None).await;
+            if let Err(e) = &synced {
+                log::info!("Error syncing {}: {}", collection.slug, e)
+            }
+
+            if let Err(e) = classify_sales(&mut conn, &collection.slug).await {
+                log::info!("Error flagging sales: {}", e)
+            }
+
+            let index_pool = pool.clone();
+            let slug = collection.slug.clone();
+            jobs.spawn(format!("trait index {}", slug), async move {
+                // new events and nearly all flag changes fall into the last 30 days
+                let recent = (Utc::now() - Duration::days(31)).naive_utc();
+                let built = async {
+                    let mut conn = index_pool.acquire().await?;
+                    let since = get_trait_index_since(&mut conn, &slug, &recent).await?;
+                    build_trait_index(&mut conn, &slug, &since).await
+                };
+                if let Err(e) = built.await {
+                    log::info!("Error building trait index: {}", e)
+                }
+            });
+
+            if let Err(e) =
+                refit_hedonic_model(&mut conn, &collection.slug, &Utc::now().naive_utc()).await
+            {
+                log::info!("Error fitting hedonic model: {}", e)
+            }
+
+            if synced.is_ok() {
+                let pool = pool.clone();
+                let slug = collection.slug.clone();
+                jobs.spawn(format!("snapshot {}", slug), async move {
+                    if let Err(e) = snapshot_collection(pool, &slug).await {
+                        log::info!("Error snapshotting prices: {}", e)
+                    }
+                });
+            }
         }
     }
 }
@@ -31,19 +97,20 @@ pub async fn sync_collection(
 ) -> Result<()> {
     let client = OpenseaAPIClient::new(1);
 
-    // Sync Collection Floor
-    let floor = client
-        .get_collection(&collection.slug)
-        .await?
-        .collection
-        .stats
-        .floor_price
-        .unwrap_or_default();
+    // Sync Collection Floor and Fees
+    let os_collection = client.get_collection(&collection.slug).await?.collection;
+    let floor = os_collection.stats.floor_price.unwrap_or_default();
 
     update_collection_floor(conn, &collection.slug, floor)
         .await
         .unwrap_or_default();
 
+    if let Some(contract) = os_collection.primary_asset_contracts.first() {
+        update_collection_fees(conn, &collection.slug, contract)
+            .await
+            .unwrap_or_default();
+    }
+
     let latest_event = match read_latests_listing_for_collection(conn, &collection.slug).await {
         Ok(l) => l,
         Err(_) => return Ok(()),
@@ -131,38 +198,59 @@ pub async fn sync_collection(
         }
     }
 
+    // Sync Bids
+    let latest_bid = read_latest_bid_for_collection(conn, &collection.slug)
+        .await?
+        .unwrap_or(latest_event);
+
+    let mut bids = vec![];
+    let mut bids_fetched = true;
+    for update_type in ["offer_entered", "bid_entered", "bid_withdrawn"] {
+        match fetch_bids(
+            &client,
+            &collection.address,
+            update_type,
+            occurred_after_listings.unwrap_or(&NaiveDateTime::from_timestamp(latest_bid as i64, 0)),
+        )
+        .await
+        {
+            Ok(events) => bids.extend(events.into_iter().map(|e| (update_type, e))),
+            Err(e) => {
+                log::info!("Error fetching {} events: {}", update_type, e);
+                bids_fetched = false;
+                break;
+            }
+        }
+    }
+
+    // storing only some of the types or chunks would move the latest bid past the missing ones
+    // for good, so nothing is stored and the next sync fetches them again
+    if bids_fetched {
+        for (update_type, event) in bids {
+            if let Err(e) = write_bid(conn, &event, &collection.slug, update_type).await {
+                log::info!("Error Storing: {} \n {:?}", e, event)
+            }
+        }
+    }
+
     let latest_sale = match read_latest_sale_for_collection(conn, &collection.slug).await {
         Ok(l) => l,
         Err(_) => return Ok(()),
     };
 
     // Sync Transfers
+    let owners_synced_at = Utc::now().naive_utc();
     let transfers = fetch_collection_transfers(
         &client,
         &collection.address,
         occurred_after_listings.unwrap_or(&NaiveDateTime::from_timestamp(latest_sale as i64, 0)),
     )
-    .await
-    .unwrap_or_default();
-
-    for e in &transfers {
-        if e.to_account.is_some() {
-            let token_id = if e.asset.is_some() {
-                e.asset.as_ref().unwrap().token_id as i32
-            } else {
-                return Ok(());
-            };
-
-            let new_owner = if e.to_account.is_some() {
-                e.to_account.as_ref().unwrap().address.clone()
-            } else {
-                return Ok(());
-            };
-            write_transfer(conn, token_id, new_owner, &collection.slug)
-                .await
-                .unwrap_or_default();
-        }
-    }
+    .await;
+    let transfers_synced = transfers.is_ok();
+    let transfers = transfers.unwrap_or_else(|e| {
+        log::info!("Error fetching transfers: {}", e);
+        vec![]
+    });
 
     // Sync Sales
     let sales = fetch_collection_sales(
@@ -170,34 +258,101 @@ pub async fn sync_collection(
         &collection.address,
         occurred_after_sales.unwrap_or(&NaiveDateTime::from_timestamp(latest_sale as i64, 0)),
     )
-    .await
-    .unwrap_or_default();
+    .await;
+    let sales_synced = sales.is_ok();
+    let sales = sales.unwrap_or_else(|e| {
+        log::info!("Error fetching sales: {}", e);
+        vec![]
+    });
 
+    // OpenSea returns the current price of the payment token, so the rate is stored at sync time
+    if let Some(p) = sales
+        .iter()
+        .filter_map(|e| e.payment_token.as_ref())
+        .find(|p| p.eth_price > 0f64)
+    {
+        let rate = FxRate {
+            timestamp: Utc::now().timestamp() as i32,
+            eth_usd: p.usd_price / p.eth_price,
+        };
+        write_fx_rates(conn, &[rate]).await.unwrap_or_default();
+    }
+
+    let mut failed_writes = 0;
     for e in &sales {
-        if let Some(p) = e.payment_token.clone() {
-            if p.symbol == "ETH" {
-                write_sale(conn, e, &collection.slug)
-                    .await
-                    .unwrap_or_default();
-
-                let token_id = if e.asset.is_some() {
-                    e.asset.as_ref().unwrap().token_id as i32
-                } else {
-                    return Ok(());
-                };
+        // Sales in other currencies than ETH are converted by write_sale
+        if let Err(err) = write_sale(conn, e, &collection.slug).await {
+            log::info!("Error Storing: {} \n {:?}", err, e);
+            failed_writes += 1;
+        }
+    }
 
-                let new_owner = if e.winner_account.is_some() {
-                    e.winner_account.as_ref().unwrap().address.clone()
-                } else {
-                    return Ok(());
-                };
+    // Sync Owners, a token may have changed hands several times so the oldest owner goes first
+    let mut owner_changes = transfers
+        .iter()
+        .map(|e| (e, &e.to_account))
+        .chain(sales.iter().map(|e| (e, &e.winner_account)))
+        .filter_map(|(e, account)| {
+            Some((
+                e.created_date,
+                e.asset.as_ref()?.token_id as i32,
+                account.as_ref()?.address.clone(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    owner_changes.sort_by_key(|(timestamp, _, _)| *timestamp);
 
-                write_transfer(conn, token_id, new_owner, &collection.slug)
-                    .await
-                    .unwrap_or_default();
-            }
+    for (_, token_id, new_owner) in owner_changes {
+        if let Err(e) = write_transfer(conn, token_id, new_owner, &collection.slug).await {
+            log::info!("Error Storing owner of {}: {}", token_id, e);
+            failed_writes += 1;
         }
     }
 
+    if let Some(synced_at) = get_owners_synced_at(
+        transfers_synced && sales_synced,
+        failed_writes,
+        owners_synced_at,
+    ) {
+        update_collection_owners_synced_at(conn, &collection.slug, &synced_at)
+            .await
+            .unwrap_or_default();
+    }
+
     Ok(())
 }
+
+/// Owners can only be trusted if every transfer and sale since the last sync was fetched and
+/// stored, else None and the previous sync time is kept
+fn get_owners_synced_at(
+    fetched: bool,
+    failed_writes: usize,
+    synced_at: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    if fetched && failed_writes == 0 {
+        Some(synced_at)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opensea::os_client::merge_event_chunks;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_failed_chunk_keeps_owners_synced_at() {
+        let now = Utc::now().naive_utc();
+        let transfers = merge_event_chunks(vec![Ok(vec![1]), Err(anyhow!("timeout"))]);
+        let sales = merge_event_chunks(vec![Ok(vec![2])]);
+
+        assert_eq!(
+            get_owners_synced_at(transfers.is_ok() && sales.is_ok(), 0, now),
+            None
+        );
+        assert_eq!(get_owners_synced_at(sales.is_ok(), 1, now), None);
+        assert_eq!(get_owners_synced_at(sales.is_ok(), 0, now), Some(now));
+    }
+}